@@ -0,0 +1,126 @@
+use crossbeam_channel::Sender;
+use rand::Rng;
+use wg_2024::network::NodeId;
+use wg_2024::packet::{Packet, PacketType};
+
+use super::debug::DebugReason;
+use super::faults::FaultEvent;
+use super::FungiDrone;
+
+/// Adversarial behaviour of the drone, none by default.
+/// Every action taken is reported on the fault channel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Byzantine {
+    /// Drops every MsgFragment without sending a Nack
+    Blackhole,
+    /// Drops the MsgFragments matching the filter without sending a Nack
+    Greyhole(TrafficFilter),
+    /// Sends the packets matching the filter to a neighbor which isn't the next hop
+    Misrouting(TrafficFilter),
+}
+
+/// Selects the traffic targeted by a [`Byzantine`] mode.
+/// An empty list matches everything, the default filter matches all traffic.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TrafficFilter {
+    pub sessions: Vec<u64>,
+    /// Source of the packet, the first hop of its routing header
+    pub sources: Vec<NodeId>,
+}
+
+impl TrafficFilter {
+    pub fn matches(&self, packet: &Packet) -> bool {
+        let session_matches = self.sessions.is_empty() || self.sessions.contains(&packet.session_id);
+        let source_matches = self.sources.is_empty()
+            || matches!(packet.routing_header.hops.first(), Some(source) if self.sources.contains(source));
+        session_matches && source_matches
+    }
+}
+
+impl FungiDrone {
+    /// Applies the drone's byzantine mode to a packet ready to be forwarded
+    ///
+    /// Returns ```Some((Packet, NodeId, Sender))``` with where the packet should actually go
+    /// Returns ```None``` if the packet has been swallowed
+    pub(super) fn apply_byzantine(
+        &mut self,
+        p: Packet,
+        next_id: NodeId,
+        p_sender: Sender<Packet>,
+    ) -> Option<(Packet, NodeId, Sender<Packet>)> {
+        let Some(byzantine) = &self.byzantine else {
+            return Some((p, next_id, p_sender));
+        };
+
+        let is_fragment = matches!(p.pack_type, PacketType::MsgFragment(_));
+        match byzantine {
+            Byzantine::Blackhole if is_fragment => {
+                self.swallow(p);
+                None
+            }
+            Byzantine::Greyhole(filter) if is_fragment && filter.matches(&p) => {
+                self.swallow(p);
+                None
+            }
+            Byzantine::Misrouting(filter) if filter.matches(&p) => {
+                Some(self.misroute(p, next_id, p_sender))
+            }
+            _ => Some((p, next_id, p_sender)),
+        }
+    }
+
+    /// Drops a packet without telling anyone but the fault channel
+    fn swallow(&self, p: Packet) {
+        self.debug(DebugReason::Swallowed, None);
+        self.metrics.count_dropped();
+        self.report_fault(FaultEvent::Swallowed {
+            drone: self.id,
+            packet: p,
+        });
+    }
+
+    /// Picks a random neighbor other than the next hop.
+    /// If the next hop is the only neighbor the packet goes there anyway.
+    fn misroute(
+        &mut self,
+        p: Packet,
+        next_id: NodeId,
+        p_sender: Sender<Packet>,
+    ) -> (Packet, NodeId, Sender<Packet>) {
+        let mut wrong_neighbors = self
+            .packet_send
+            .keys()
+            .filter(|&&id| id != next_id)
+            .copied()
+            .collect::<Vec<NodeId>>();
+
+        if wrong_neighbors.is_empty() {
+            return (p, next_id, p_sender);
+        }
+
+        // Sorted so that the seeded generator always picks the same neighbor
+        wrong_neighbors.sort_unstable();
+        let wrong_id = wrong_neighbors[self.rng.gen_range(0..wrong_neighbors.len())];
+        let wrong_sender = self.packet_send[&wrong_id].clone();
+
+        self.debug(
+            DebugReason::Misrouted {
+                next_hop: next_id,
+                sent_to: wrong_id,
+            },
+            None,
+        );
+        self.report_fault(FaultEvent::Misrouted {
+            drone: self.id,
+            packet: p.clone(),
+            next_hop: next_id,
+            sent_to: wrong_id,
+        });
+        (p, wrong_id, wrong_sender)
+    }
+
+    /// Makes the drone misbehave on purpose, `None` to make it behave again
+    pub fn set_byzantine(&mut self, byzantine: Option<Byzantine>) {
+        self.byzantine = byzantine;
+    }
+}
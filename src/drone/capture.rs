@@ -0,0 +1,335 @@
+//! Packet capture, in the `fungi-capture` JSON-lines format.
+//!
+//! ## Format, version 1
+//!
+//! A capture is a UTF-8 text file with one JSON object per line.
+//! The first line is the [`CaptureHeader`]:
+//!
+//! ``` json
+//! {"format":"fungi-capture","version":1,"drone":2,"seed":42,"started_at_ms":1729152000000,"neighbors":[1,3],"pdr":0.1}
+//! ```
+//!
+//! `neighbors` and `pdr` are the links and drop rate of the drone when the capture started.
+//!
+//! Every other line is a [`CaptureRecord`], tagged by its `kind`:
+//!
+//! ``` json
+//! {"kind":"packet","time_us":1520,"direction":"in","neighbor":1,"packet":{...}}
+//! {"kind":"command","time_us":1730,"command":{"RemoveSender":3}}
+//! {"kind":"recovered","time_us":2950}
+//! {"kind":"reseeded","time_us":3100,"seed":7}
+//! ```
+//!
+//! - `time_us`: microseconds since the capture started, on a monotonic clock
+//! - `direction`: `"in"` for a packet received by the drone, `"out"` for one it forwards
+//! - `neighbor`: who sent the packet or who it is forwarded to, `null` if a received packet doesn't tell
+//! - `packet`: the full `Packet`, as serialized by the `serialize` feature of `wg_2024`
+//! - `command`: a [`CapturedCommand`] received from the simulation controller.
+//!   Commands received on the fungi command channel are not captured
+//! - `recovered` records mark the drone coming back online after a crash,
+//!   on a `FungiCommand::Recover` or after its recovery delay
+//! - `reseeded` records mark the drone's random number generator being seeded again with `seed`
+//!
+//! Records are written in the order the drone handles them.
+//! Readers must skip the records whose `kind` they don't know,
+//! new kinds can be added without changing the version.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufWriter, Write};
+use std::path::Path;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use rand_xoshiro::rand_core::SeedableRng;
+use rand_xoshiro::Xoshiro256PlusPlus;
+use serde::{Deserialize, Serialize};
+use wg_2024::controller::DroneCommand;
+use wg_2024::network::NodeId;
+use wg_2024::packet::Packet;
+
+use super::debug::DebugReason;
+use super::{header, FungiDrone};
+
+/// Name in the header of every capture
+pub const CAPTURE_FORMAT: &str = "fungi-capture";
+/// Version of the capture format written by this crate
+pub const CAPTURE_VERSION: u32 = 1;
+
+/// First line of a capture
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CaptureHeader {
+    /// Always [`CAPTURE_FORMAT`]
+    pub format: String,
+    pub version: u32,
+    /// Id of the captured drone
+    pub drone: NodeId,
+    /// Seed of the drone's random number generator when the capture started,
+    /// the generator is seeded again with it as the capture starts
+    pub seed: u64,
+    /// Wall-clock time at which the capture started, in milliseconds since the Unix epoch
+    pub started_at_ms: u64,
+    /// Neighbors of the drone when the capture started
+    #[serde(default)]
+    pub neighbors: Vec<NodeId>,
+    /// Packet drop rate of the drone when the capture started
+    #[serde(default)]
+    pub pdr: f32,
+}
+
+/// Whether a packet was received or forwarded by the drone
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    In,
+    Out,
+}
+
+/// A line of a capture after the header
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CaptureRecord {
+    Packet {
+        time_us: u64,
+        direction: Direction,
+        neighbor: Option<NodeId>,
+        packet: Packet,
+    },
+    Command {
+        time_us: u64,
+        command: CapturedCommand,
+    },
+    Recovered {
+        time_us: u64,
+    },
+    Reseeded {
+        time_us: u64,
+        seed: u64,
+    },
+}
+
+impl CaptureRecord {
+    /// The kinds of record this version of the crate knows
+    const KINDS: [&'static str; 4] = ["packet", "command", "recovered", "reseeded"];
+}
+
+/// A `DroneCommand`, without the channel it may carry
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum CapturedCommand {
+    AddSender(NodeId),
+    RemoveSender(NodeId),
+    SetPacketDropRate(f32),
+    Crash,
+}
+
+impl CapturedCommand {
+    pub fn of(command: &DroneCommand) -> Self {
+        match command {
+            DroneCommand::AddSender(id, _) => CapturedCommand::AddSender(*id),
+            DroneCommand::RemoveSender(id) => CapturedCommand::RemoveSender(*id),
+            DroneCommand::SetPacketDropRate(pdr) => CapturedCommand::SetPacketDropRate(*pdr),
+            DroneCommand::Crash => CapturedCommand::Crash,
+        }
+    }
+}
+
+/// A capture read back from a file
+#[derive(Debug, Clone)]
+pub struct Trace {
+    pub header: CaptureHeader,
+    pub records: Vec<CaptureRecord>,
+}
+
+impl Trace {
+    /// Reads a capture, skipping the records of unknown kinds.
+    /// Captures of another format or of a newer version are rejected
+    pub fn read(reader: impl BufRead) -> io::Result<Trace> {
+        let mut lines = reader.lines();
+        let Some(first) = lines.next() else {
+            return Err(invalid_data("the capture is empty"));
+        };
+
+        let header: CaptureHeader = serde_json::from_str(&first?)?;
+        if header.format != CAPTURE_FORMAT || header.version > CAPTURE_VERSION {
+            return Err(invalid_data(&format!(
+                "unsupported capture {} version {}",
+                header.format, header.version
+            )));
+        }
+
+        let mut records = Vec::new();
+        for line in lines {
+            let value: serde_json::Value = serde_json::from_str(&line?)?;
+            let known = value
+                .get("kind")
+                .and_then(|kind| kind.as_str())
+                .is_some_and(|kind| CaptureRecord::KINDS.contains(&kind));
+            if known {
+                records.push(serde_json::from_value(value)?);
+            }
+        }
+        Ok(Trace { header, records })
+    }
+
+    /// Reads the capture file at `path`, see [`Trace::read`]
+    pub fn read_file(path: impl AsRef<Path>) -> io::Result<Trace> {
+        Trace::read(io::BufReader::new(File::open(path)?))
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// A capture being written
+pub(super) struct Capture {
+    writer: Box<dyn Write + Send>,
+    started_at: Instant,
+}
+
+impl std::fmt::Debug for Capture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Capture")
+            .field("started_at", &self.started_at)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Capture {
+    fn write_line<T: Serialize>(&mut self, value: &T) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, value)?;
+        self.writer.write_all(b"\n")
+    }
+}
+
+impl FungiDrone {
+    /// Records a packet received by the drone, if capturing
+    pub(super) fn capture_received(&mut self, packet: &Packet) {
+        self.capture_packet(Direction::In, header::previous_hop(packet), packet);
+    }
+
+    /// Records a command from the simulation controller, if capturing
+    pub(super) fn capture_command(&mut self, command: &DroneCommand) {
+        let Some(capture) = &mut self.capture else {
+            return;
+        };
+
+        let record = CaptureRecord::Command {
+            time_us: capture.started_at.elapsed().as_micros() as u64,
+            command: CapturedCommand::of(command),
+        };
+        if let Err(error) = capture.write_line(&record) {
+            self.capture_failed(error);
+        }
+    }
+
+    /// Records the drone recovering from a crash, if capturing
+    pub(super) fn capture_recovery(&mut self) {
+        let Some(capture) = &mut self.capture else {
+            return;
+        };
+
+        let record = CaptureRecord::Recovered {
+            time_us: capture.started_at.elapsed().as_micros() as u64,
+        };
+        if let Err(error) = capture.write_line(&record) {
+            self.capture_failed(error);
+        }
+    }
+
+    /// Records the drone's random number generator being seeded with `seed`, if capturing
+    pub(super) fn capture_reseed(&mut self, seed: u64) {
+        let Some(capture) = &mut self.capture else {
+            return;
+        };
+
+        let record = CaptureRecord::Reseeded {
+            time_us: capture.started_at.elapsed().as_micros() as u64,
+            seed,
+        };
+        if let Err(error) = capture.write_line(&record) {
+            self.capture_failed(error);
+        }
+    }
+
+    /// Records a packet forwarded to `next_id`, if capturing
+    pub(super) fn capture_forwarded(&mut self, packet: &Packet, next_id: NodeId) {
+        self.capture_packet(Direction::Out, Some(next_id), packet);
+    }
+
+    fn capture_packet(&mut self, direction: Direction, neighbor: Option<NodeId>, packet: &Packet) {
+        let Some(capture) = &mut self.capture else {
+            return;
+        };
+
+        let record = CaptureRecord::Packet {
+            time_us: capture.started_at.elapsed().as_micros() as u64,
+            direction,
+            neighbor,
+            packet: packet.clone(),
+        };
+        if let Err(error) = capture.write_line(&record) {
+            self.capture_failed(error);
+        }
+    }
+
+    /// Stops capturing after a write error, the capture written so far is kept
+    fn capture_failed(&mut self, error: io::Error) {
+        self.capture = None;
+        self.debug(DebugReason::CaptureFailed(error.to_string()), None);
+    }
+
+    /// Writes out what is still buffered in the capture
+    pub(super) fn flush_capture(&mut self) {
+        if let Some(capture) = &mut self.capture {
+            if let Err(error) = capture.writer.flush() {
+                self.capture_failed(error);
+            }
+        }
+    }
+
+    /// Starts capturing every packet the drone receives and forwards,
+    /// and every command of the simulation controller, to `writer`,
+    /// in the format described in the [`capture`](self) module.
+    /// The header is written right away, an error is returned if it can't be.
+    ///
+    /// The random number generator is seeded again with [`FungiDrone::seed`], so that a replay
+    /// starting from the seed in the header draws the same numbers even if the drone already drew some.
+    /// Later calls to [`FungiDrone::set_seed`] are captured.
+    pub fn set_capture<W: Write + Send + 'static>(&mut self, writer: W) -> io::Result<()> {
+        let started_at_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64);
+
+        let mut neighbors = self.packet_send.keys().copied().collect::<Vec<NodeId>>();
+        neighbors.sort_unstable();
+
+        let mut capture = Capture {
+            writer: Box::new(writer),
+            started_at: Instant::now(),
+        };
+        capture.write_line(&CaptureHeader {
+            format: CAPTURE_FORMAT.to_string(),
+            version: CAPTURE_VERSION,
+            drone: self.id,
+            seed: self.seed,
+            started_at_ms,
+            neighbors,
+            pdr: self.pdr,
+        })?;
+
+        self.capture = Some(capture);
+        self.rng = Xoshiro256PlusPlus::seed_from_u64(self.seed);
+        Ok(())
+    }
+
+    /// Starts capturing to a new file at `path`, see [`FungiDrone::set_capture`]
+    pub fn capture_to_file(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let file = File::create(path)?;
+        self.set_capture(BufWriter::new(file))
+    }
+
+    /// Stops capturing, flushing the capture
+    pub fn stop_capture(&mut self) {
+        self.flush_capture();
+        self.capture = None;
+    }
+}
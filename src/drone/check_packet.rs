@@ -1,24 +1,142 @@
-use check::CheckError;
-use wg_2024::{controller::DroneEvent, packet::{Nack, Packet}};
+use std::fmt::Debug;
 
-use super::{generate, header, FungiDrone};
+use wg_2024::network::NodeId;
+use wg_2024::{controller::DroneEvent, packet::Packet};
+
+use super::debug::packet_span;
+use super::{generate, FungiDrone};
+
+/// The reason a [`PacketCheck`] stopped a packet from going through the pipeline
+#[derive(Debug)]
+pub enum CheckError {
+    /// The packet can't be routed and has to be delivered by the simulation controller
+    MustShortcut(Packet),
+    /// The packet is replaced by the contained Nack, which is ready to be sent
+    SendNack(Packet),
+    /// The packet has been dropped, the previous hop is notified with a Nack
+    Dropped(Packet),
+    /// The packet is discarded, the check already called the debug system
+    Debug,
+    /// An outcome defined outside of this crate
+    Custom(Box<dyn CheckOutcome>),
+}
+
+/// A check outcome which isn't covered by [`CheckError`].
+pub trait CheckOutcome: Debug + Send {
+    /// Resolves the outcome once the pipeline has stopped.
+    ///
+    /// Returns ```Some(Packet)``` if a packet should be forwarded in place of the checked one
+    /// Returns ```None``` if nothing else should be sent
+    fn resolve(self: Box<Self>, drone: &FungiDrone) -> Option<Packet>;
+}
+
+/// A single step of the [`CheckPipeline`].
+///
+/// While the checks run the pipeline is taken out of the drone, so a check sees an empty
+/// [`FungiDrone::check_pipeline_mut`] and any change it makes to the pipeline is lost.
+pub trait PacketCheck: Debug + Send {
+    /// Returns ```Ok(Packet)``` to hand the packet to the next check.
+    /// Returns ```Err(CheckError)``` to stop the pipeline.
+    fn check(&self, packet: Packet, drone: &mut FungiDrone) -> Result<Packet, CheckError>;
+}
+
+/// Ordered list of checks every routed packet goes through before being forwarded.
+///
+/// The default pipeline is the one described by the protocol:
+/// [`check::IdMatchesHop`], [`check::AdvanceHop`], [`check::DestinationIsDrone`],
+/// [`check::MessageDrop`], [`check::NotNeighbor`].
+/// The built-in checks after [`check::AdvanceHop`] expect the hop index to already point to the next hop.
+#[derive(Debug)]
+pub struct CheckPipeline {
+    checks: Vec<Box<dyn PacketCheck>>,
+}
+
+impl Default for CheckPipeline {
+    fn default() -> Self {
+        let mut pipeline = Self::empty();
+        pipeline
+            .push(check::IdMatchesHop)
+            .push(check::AdvanceHop)
+            .push(check::DestinationIsDrone)
+            .push(check::MessageDrop)
+            .push(check::NotNeighbor);
+        pipeline
+    }
+}
+
+impl CheckPipeline {
+    /// A pipeline without any check, every packet goes straight to forwarding
+    pub fn empty() -> Self {
+        Self { checks: Vec::new() }
+    }
+
+    /// Appends a check at the end of the pipeline
+    pub fn push<C: PacketCheck + 'static>(&mut self, check: C) -> &mut Self {
+        self.checks.push(Box::new(check));
+        self
+    }
+
+    /// Inserts a check at position `index`, shifting all checks after it
+    ///
+    /// ## Panics
+    /// If `index > len`
+    pub fn insert<C: PacketCheck + 'static>(&mut self, index: usize, check: C) -> &mut Self {
+        self.checks.insert(index, Box::new(check));
+        self
+    }
+
+    /// Removes and returns the check at position `index`
+    ///
+    /// ## Panics
+    /// If `index` is out of bounds
+    pub fn remove(&mut self, index: usize) -> Box<dyn PacketCheck> {
+        self.checks.remove(index)
+    }
+
+    /// Moves the check at position `from` to position `to`
+    ///
+    /// ## Panics
+    /// If either index is out of bounds
+    pub fn move_check(&mut self, from: usize, to: usize) -> &mut Self {
+        let check = self.checks.remove(from);
+        self.checks.insert(to, check);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.checks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.checks.is_empty()
+    }
+
+    fn run(&self, mut packet: Packet, drone: &mut FungiDrone) -> Result<Packet, CheckError> {
+        for check in &self.checks {
+            packet = check.check(packet, drone)?;
+        }
+        Ok(packet)
+    }
+}
 
 impl FungiDrone {
     /// Ensure a packet is correct before forwarding
     /// Returns ```Ok(Packet)``` to give back ownership if packet is valid.
     /// Returns ```Err(CheckError)``` to express error response
     pub(super) fn check_packet(&mut self, packet: Packet) -> Result<Packet, CheckError> {
-        let mut packet = check::id_matches_hop(packet, self)?;
-
-        header::increment_index(&mut packet.routing_header);
-
-        packet = check::destination_is_drone(packet)?;
+        let span = packet_span!("check_packet", self.id, &packet);
+        let _enter = span.enter();
 
-        packet = check::message_drop(packet, self)?;
+        // The pipeline is taken out of the drone for the duration of the checks,
+        // since every check needs mutable access to the drone
+        let pipeline = std::mem::replace(&mut self.checks, CheckPipeline::empty());
+        let res = pipeline.run(packet, self);
+        self.checks = pipeline;
 
-        packet = check::not_neighbor(packet, self)?;
-
-        Ok(packet)
+        if let Err(error) = &res {
+            tracing::debug!(?error, "check failed");
+        }
+        res
     }
 
     /// Control next action based on check result.
@@ -35,7 +153,10 @@ impl FungiDrone {
                 self.send_controller(DroneEvent::ControllerShortcut(err));
                 None
             }
-            CheckError::SendNack(err) => Some(err),
+            CheckError::SendNack(err) => {
+                self.metrics.count_nack(&err);
+                Some(err)
+            }
             CheckError::Debug => None,
             CheckError::Dropped(mut packet) => {
                self.log_action(packet.clone(), true);
@@ -43,110 +164,155 @@ impl FungiDrone {
 
                if let wg_2024::packet::PacketType::MsgFragment(fragment) = packet.pack_type {
                 let dropped_packet = generate::dropped_packet(packet.routing_header, packet.session_id, fragment);
+                self.metrics.count_nack(&dropped_packet);
                 return Some(dropped_packet);
               }
 
               return None;
             },
+            CheckError::Custom(outcome) => outcome.resolve(self),
         }
     }
-}
 
-mod check {
-    use crate::drone::{generate, header};
+    /// The drone's neighbors, in increasing order.
+    /// Useful to checks and outcomes which need to pick where a packet goes.
+    pub fn neighbors(&self) -> Vec<NodeId> {
+        let mut neighbors = self.packet_send.keys().copied().collect::<Vec<NodeId>>();
+        neighbors.sort_unstable();
+        neighbors
+    }
 
-    use super::super::drone::FungiDrone;
-    use wg_2024::packet::{Packet, PacketType};
+    /// Sends an event to the simulation controller, for checks and outcomes which report what they did
+    pub fn notify_controller(&self, event: DroneEvent) {
+        self.send_controller(event);
+    }
 
-    #[derive(Debug)]
-    pub enum CheckError {
-        MustShortcut(Packet),
-        SendNack(Packet),
-        Dropped(Packet),
-        Debug,
+    /// Replaces the checks done on every routed packet.
+    /// Has no effect when called from a [`PacketCheck`], the pipeline in use is put back once the checks are done
+    pub fn set_check_pipeline(&mut self, pipeline: CheckPipeline) {
+        self.checks = pipeline;
     }
 
-    /// Checks if the current drone matches the hop index position of the hop vector
-    /// ## Arguments
-    /// - `p`: The packet to be checked
-    /// - `d`: The current drone
-    pub fn id_matches_hop(mut p: Packet, d: &FungiDrone) -> Result<Packet, CheckError> {
-        let hop_res = header::get_hop(&p.routing_header);
-        if hop_res.is_none() {
-            d.debug("Hop index beyond hops length", Some(p));
-            return Err(CheckError::Debug);
-        }
+    /// Gives access to the checks done on every routed packet, to add, remove or reorder them.
+    /// From a [`PacketCheck`] it gives an empty pipeline, and the changes made to it are lost
+    pub fn check_pipeline_mut(&mut self) -> &mut CheckPipeline {
+        &mut self.checks
+    }
+}
 
-        let hop = hop_res.unwrap();
+/// The checks required by the protocol, in the order of [`CheckPipeline::default`]
+pub mod check {
+    use crate::drone::{generate, header, DebugReason};
 
-        if d.id == hop {
-            return Ok(p);
-        }
+    use super::super::drone::FungiDrone;
+    use super::{CheckError, PacketCheck};
+    use wg_2024::packet::{Packet, PacketType};
 
-        if let PacketType::MsgFragment(f) = p.pack_type {
-            header::increment_index(&mut p.routing_header);
+    /// Checks if the current drone matches the hop index position of the hop vector
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct IdMatchesHop;
 
-            let err_p =
-                generate::unexpected(p.routing_header, p.session_id, d.id, f.fragment_index);
-            return Err(CheckError::SendNack(err_p));
-        }
+    impl PacketCheck for IdMatchesHop {
+        fn check(&self, mut p: Packet, d: &mut FungiDrone) -> Result<Packet, CheckError> {
+            let hop_res = header::get_hop(&p.routing_header);
+            if hop_res.is_none() {
+                d.debug(DebugReason::HopIndexOutOfRange, Some(p));
+                return Err(CheckError::Debug);
+            }
 
-        Err(CheckError::MustShortcut(p))
-    }
+            let hop = hop_res.unwrap();
+
+            if d.id == hop {
+                return Ok(p);
+            }
 
-    /// Checks if the current drone is the last hop
-    /// ## Arguments
-    /// - `p`: The packet to be checked
-    pub fn destination_is_drone(p: Packet) -> Result<Packet, CheckError> {
-        if p.routing_header.hop_index == p.routing_header.hops.len() {
             if let PacketType::MsgFragment(f) = p.pack_type {
+                header::increment_index(&mut p.routing_header);
+
                 let err_p =
-                    generate::destination_drone(p.routing_header, p.session_id, f.fragment_index);
+                    generate::unexpected(p.routing_header, p.session_id, d.id, f.fragment_index);
                 return Err(CheckError::SendNack(err_p));
             }
-            return Err(CheckError::MustShortcut(p));
+
+            Err(CheckError::MustShortcut(p))
         }
-        Ok(p)
     }
 
-    /// Rolls to see if packet is dropped
-    /// ## Arguments
-    /// - `p`: The packet to be checked
-    /// - `d`: The current drone
-    pub fn message_drop(mut p: Packet, d: &FungiDrone) -> Result<Packet, CheckError> {
-        // We pattern match and then unwrap with if let to avoid cloning
-        if !matches!(&p.pack_type, PacketType::MsgFragment(_)) {
-            return Ok(p);
-        }
-        if FungiDrone::dropped(d) {
-            p.routing_header.decrease_hop_index();
-            return Err(CheckError::Dropped(p));
+    /// Moves the hop index from the current drone to the next hop
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct AdvanceHop;
+
+    impl PacketCheck for AdvanceHop {
+        fn check(&self, mut p: Packet, _d: &mut FungiDrone) -> Result<Packet, CheckError> {
+            header::increment_index(&mut p.routing_header);
+            Ok(p)
         }
-        Ok(p)
-    }
-
-    /// Rolls to see if packet is dropped
-    /// ## Arguments
-    /// - `p`: The packet to be checked
-    /// - `d`: The current drone
-    pub fn not_neighbor(p: Packet, d: &FungiDrone) -> Result<Packet, CheckError> {
-        if let Some(next_hop) = header::get_hop(&p.routing_header) {
-            if !d.packet_send.contains_key(&next_hop) {
-                if let PacketType::MsgFragment(msg) = p.pack_type {
-                    let err_p = generate::route_error(
-                        p.routing_header,
-                        p.session_id,
-                        next_hop,
-                        msg.fragment_index,
-                    );
+    }
+
+    /// Checks if the current drone is the last hop
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct DestinationIsDrone;
+
+    impl PacketCheck for DestinationIsDrone {
+        fn check(&self, p: Packet, _d: &mut FungiDrone) -> Result<Packet, CheckError> {
+            if p.routing_header.hop_index == p.routing_header.hops.len() {
+                if let PacketType::MsgFragment(f) = p.pack_type {
+                    let err_p =
+                        generate::destination_drone(p.routing_header, p.session_id, f.fragment_index);
                     return Err(CheckError::SendNack(err_p));
                 }
-
                 return Err(CheckError::MustShortcut(p));
             }
-            return Ok(p);
+            Ok(p)
+        }
+    }
+
+    /// Rolls to see if packet is dropped on the link towards the next hop
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct MessageDrop;
+
+    impl PacketCheck for MessageDrop {
+        fn check(&self, mut p: Packet, d: &mut FungiDrone) -> Result<Packet, CheckError> {
+            // We pattern match and then unwrap with if let to avoid cloning
+            if !matches!(&p.pack_type, PacketType::MsgFragment(_)) {
+                return Ok(p);
+            }
+            // Unknown next hops are left to the following checks
+            let Some(next_hop) = header::get_hop(&p.routing_header) else {
+                return Ok(p);
+            };
+            if d.dropped(next_hop) {
+                p.routing_header.decrease_hop_index();
+                return Err(CheckError::Dropped(p));
+            }
+            Ok(p)
+        }
+    }
+
+    /// Checks if the next hop is a neighbor of the current drone
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct NotNeighbor;
+
+    impl PacketCheck for NotNeighbor {
+        fn check(&self, p: Packet, d: &mut FungiDrone) -> Result<Packet, CheckError> {
+            if let Some(next_hop) = header::get_hop(&p.routing_header) {
+                if !d.packet_send.contains_key(&next_hop) {
+                    if let PacketType::MsgFragment(msg) = p.pack_type {
+                        let err_p = generate::route_error(
+                            p.routing_header,
+                            p.session_id,
+                            next_hop,
+                            msg.fragment_index,
+                        );
+                        return Err(CheckError::SendNack(err_p));
+                    }
+
+                    return Err(CheckError::MustShortcut(p));
+                }
+                return Ok(p);
+            }
+            d.debug(DebugReason::HopIndexOutOfRange, Some(p));
+            Err(CheckError::Debug)
         }
-        d.debug("hop_index beyond hops length", Some(p));
-        Err(CheckError::Debug)
     }
 }
@@ -1,21 +1,211 @@
-use super::{header, FungiDrone};
-use wg_2024::packet::{Packet, PacketType};
+use std::collections::BTreeSet;
+use std::time::{Duration, Instant};
+
+use super::debug::DebugReason;
+use super::faults::FaultEvent;
+use super::{header, FungiCommand, FungiDrone};
+use crossbeam_channel::{after, never, select_biased};
+use wg_2024::controller::{DroneCommand, DroneEvent};
+use wg_2024::network::NodeId;
+use wg_2024::packet::{NodeType, Packet, PacketType};
+
+/// A condition on which the drone crashes by itself, without waiting for a `DroneCommand::Crash`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CrashTrigger {
+    /// After the drone has forwarded this many packets, Nacks and flooding included
+    AfterForwarded(u64),
+    /// After this much wall-clock time, counted from when the trigger is set
+    AfterDelay(Duration),
+    /// After this many ticks of the drone's virtual clock,
+    /// which advances by one for every packet or command the drone receives
+    AfterTicks(u64),
+    /// Each packet received makes the drone crash with this probability
+    Probability(f32),
+}
+
+/// How a crashed drone comes back online.
+///
+/// A crashed drone always recovers on a [`FungiCommand::Recover`],
+/// if no command comes it stays down until all senders of its receiver are removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Recovery {
+    /// Time after which a crashed drone recovers by itself, `None` to wait for a command
+    pub recover_after: Option<Duration>,
+    /// Whether the drone forgets the floods it has seen when it recovers
+    pub reset_flood_ids: bool,
+}
+
+/// What a crashed drone does with the FloodRequests it receives
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CrashedFloodPolicy {
+    /// The request is dropped, the initiator has to wait for its timeout
+    #[default]
+    Ignore,
+    /// The drone answers with a FloodResponse which ends with itself
+    Respond,
+    /// The request is sent to the simulation controller
+    Shortcut,
+}
+
+/// What happened while a crashed drone drained its channel, sent when the drain ends
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DrainSummary {
+    /// Packets received while crashed
+    pub packets: u64,
+    /// Time between the crash and the end of the drain
+    pub duration: Duration,
+    /// Whether the drain stopped at the deadline instead of when all senders were removed
+    pub timed_out: bool,
+    /// Neighbors whose link hasn't been removed, they may still hold a sender
+    pub neighbors: Vec<NodeId>,
+    /// Neighbors which sent packets while the drone was crashed, they held a sender during the drain
+    pub heard_from: Vec<NodeId>,
+}
+
+/// The crash triggers set on the drone and what they are counting
+#[derive(Debug, Default)]
+pub(super) struct CrashSchedule {
+    triggers: Vec<CrashTrigger>,
+    deadline: Option<Instant>,
+    forwarded: u64,
+    ticks: u64,
+    rolled: bool,
+}
+
+impl CrashSchedule {
+    /// The instant at which the earliest [`CrashTrigger::AfterDelay`] fires
+    pub(super) fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    pub(super) fn count_forwarded(&mut self) {
+        self.forwarded += 1;
+    }
+
+    /// The first trigger which has fired, if any
+    fn fired(&self, now: Instant) -> Option<CrashTrigger> {
+        self.triggers.iter().copied().find(|trigger| match *trigger {
+            CrashTrigger::AfterForwarded(n) => self.forwarded >= n,
+            CrashTrigger::AfterDelay(_) => matches!(self.deadline, Some(deadline) if deadline <= now),
+            CrashTrigger::AfterTicks(n) => self.ticks >= n,
+            CrashTrigger::Probability(_) => self.rolled,
+        })
+    }
+}
 
 impl FungiDrone {
     /// Switches the drone into crash behaviour.
-    /// The drone waits for all senders of it's own receiver to be removed,
-    /// and then breaks.
-    pub(super) fn crash_behaviour(&mut self) {
-        while let Ok(packet) = self.packet_recv.recv() {
-            self.handle_packet_crashed(packet);
+    /// The drone rejects traffic as a crashed drone until either:
+    /// - it recovers, on a [`FungiCommand::Recover`] or when [`Recovery::recover_after`] has passed
+    /// - all senders of it's own receiver are removed, or the drain deadline passes,
+    ///   then it sends the packets still waiting on a slow link, reports a [`DrainSummary`] and breaks.
+    ///
+    /// Packets held by the reordering window are released as soon as the drone crashes.
+    /// The crash triggers are one-shot: all of them are cleared once the drone crashes.
+    ///
+    /// Returns `true` if the drone recovered and should go back to forwarding
+    pub(super) fn crash_behaviour(&mut self) -> bool {
+        self.enter_crash();
+
+        let crashed_at = Instant::now();
+        let recovery_timer = match self.recovery.recover_after {
+            Some(delay) => after(delay),
+            None => never(),
+        };
+        let drain_timer = match self.drain_timeout {
+            Some(timeout) => after(timeout),
+            None => never(),
+        };
+        let mut controller_recv = self.controller_recv.clone();
+        let mut packets = 0;
+        let mut heard_from = BTreeSet::new();
+
+        let timed_out = loop {
+            let timer = self.timer();
+            select_biased! {
+                recv(recovery_timer) -> _ => return self.recover(),
+                recv(self.fungi_recv) -> command_res => match command_res {
+                    Ok(FungiCommand::Recover) => return self.recover(),
+                    Ok(command) => self.handle_fungi_command(command),
+                    Err(_) => self.fungi_recv = never(),
+                },
+                recv(controller_recv) -> command_res => match command_res {
+                    Ok(command) => self.handle_command_crashed(command),
+                    Err(_) => controller_recv = never(),
+                },
+                // The deadline and the timed work go before packets, so that a busy neighbor can't hold them back
+                recv(drain_timer) -> _ => break true,
+                recv(timer) -> _ => self.on_tick(),
+                recv(self.packet_recv) -> packet_res => match packet_res {
+                    Ok(packet) => {
+                        packets += 1;
+                        heard_from.extend(header::previous_hop(&packet));
+                        self.handle_packet_crashed(packet);
+                    }
+                    Err(_) => break false,
+                },
+            }
+        };
+        self.flush_outbound();
+
+        let mut neighbors = self.packet_send.keys().copied().collect::<Vec<NodeId>>();
+        neighbors.sort_unstable();
+        if timed_out {
+            self.debug(
+                DebugReason::DrainTimedOut {
+                    neighbors: neighbors.clone(),
+                    heard_from: heard_from.iter().copied().collect(),
+                },
+                None,
+            );
+        }
+
+        self.report_fault(FaultEvent::Drained {
+            drone: self.id,
+            summary: DrainSummary {
+                packets,
+                duration: crashed_at.elapsed(),
+                timed_out,
+                neighbors,
+                heard_from: heard_from.into_iter().collect(),
+            },
+        });
+        false
+    }
+
+    /// Clears the crash triggers and releases the packets held by the reordering window
+    pub(super) fn enter_crash(&mut self) {
+        self.crash_schedule = CrashSchedule::default();
+        self.flush_reorder_window();
+    }
+
+    /// While crashed, the drone still keeps track of its links.
+    /// Another crash command does nothing.
+    pub(super) fn handle_command_crashed(&mut self, command: DroneCommand) {
+        if !matches!(command, DroneCommand::Crash) {
+            self.handle_command_internal(Ok(command));
+        }
+    }
+
+    /// Brings the drone back online, forgetting the floods it has seen if configured to
+    pub(super) fn recover(&mut self) -> bool {
+        if self.recovery.reset_flood_ids {
+            self.seen_flood_ids.clear();
         }
+
+        self.debug(DebugReason::Recovered, None);
+        self.capture_recovery();
+        self.report_fault(FaultEvent::Recovered { drone: self.id });
+        true
     }
 
     /// In crash behaviour:
     /// - MsgFragments: Return error in routing
     /// - Nack/Ack/FloodResponse: Are handled as usual
-    /// - FloodRequests: ignored :(
+    /// - FloodRequests: handled according to the [`CrashedFloodPolicy`], ignored by default
     pub(super) fn handle_packet_crashed(&mut self, mut packet: Packet) {
+        self.metrics.count_received(&packet);
+        self.capture_received(&packet);
         match packet.pack_type {
             PacketType::MsgFragment(_) => {
                 header::increment_index(&mut packet.routing_header);
@@ -34,7 +224,99 @@ impl FungiDrone {
                     self.forward(pack_ready, id, sender);
                 }
             }
-            PacketType::FloodRequest(_) => (),
+            PacketType::FloodRequest(_) => self.handle_flood_crashed(packet),
+        }
+    }
+
+    /// Handles a FloodRequest received while crashed
+    fn handle_flood_crashed(&mut self, packet: Packet) {
+        match self.crashed_flood_policy {
+            CrashedFloodPolicy::Ignore => (),
+            CrashedFloodPolicy::Respond => {
+                if let PacketType::FloodRequest(mut flood_req) = packet.pack_type {
+                    flood_req.path_trace.push((self.id, NodeType::Drone));
+                    if let Err(error) = self.respond_to_flood(flood_req, packet.session_id) {
+                        self.handle_flood_error(error);
+                    }
+                }
+            }
+            CrashedFloodPolicy::Shortcut => {
+                self.send_controller(DroneEvent::ControllerShortcut(packet))
+            }
+        }
+    }
+
+    /// Advances the drone's virtual clock after receiving a packet or a command,
+    /// and rolls the [`CrashTrigger::Probability`] triggers if it was a packet
+    pub(super) fn tick_crash_schedule(&mut self, packet: bool) {
+        self.crash_schedule.ticks += 1;
+        if !packet {
+            return;
+        }
+
+        let probabilities = self
+            .crash_schedule
+            .triggers
+            .iter()
+            .filter_map(|trigger| match trigger {
+                CrashTrigger::Probability(p) => Some(*p),
+                _ => None,
+            })
+            .collect::<Vec<f32>>();
+        for p in probabilities {
+            if self.roll(p) {
+                self.crash_schedule.rolled = true;
+            }
+        }
+    }
+
+    /// Whether one of the crash triggers has fired.
+    /// The trigger is reported to the debug system and the fault channel.
+    pub(super) fn scheduled_crash_due(&self) -> bool {
+        let Some(trigger) = self.crash_schedule.fired(Instant::now()) else {
+            return false;
+        };
+
+        self.debug(DebugReason::ScheduledCrash(trigger), None);
+        self.report_fault(FaultEvent::ScheduledCrash {
+            drone: self.id,
+            trigger,
+        });
+        true
+    }
+
+    /// Makes the drone crash by itself when `trigger` fires.
+    /// The crash goes through the same crash behaviour as a `DroneCommand::Crash`.
+    /// A delay too long to be represented as a deadline, such as `Duration::MAX`, never fires.
+    pub fn add_crash_trigger(&mut self, trigger: CrashTrigger) {
+        if let CrashTrigger::AfterDelay(delay) = trigger {
+            let Some(deadline) = Instant::now().checked_add(delay) else {
+                return;
+            };
+            let earliest = self.crash_schedule.deadline.map_or(deadline, |d| d.min(deadline));
+            self.crash_schedule.deadline = Some(earliest);
         }
+        self.crash_schedule.triggers.push(trigger);
+    }
+
+    /// Sets how the drone comes back online after crashing
+    pub fn set_recovery(&mut self, recovery: Recovery) {
+        self.recovery = recovery;
+    }
+
+    /// Sets what the drone does with the FloodRequests it receives while crashed
+    pub fn set_crashed_flood_policy(&mut self, policy: CrashedFloodPolicy) {
+        self.crashed_flood_policy = policy;
+    }
+
+    /// Stops draining the channel after `timeout` once crashed, even if some senders are still held.
+    /// `None` drains until all senders are removed.
+    pub fn set_drain_timeout(&mut self, timeout: Option<Duration>) {
+        self.drain_timeout = timeout;
+    }
+
+    /// Removes every crash trigger, the drone will only crash on a `DroneCommand::Crash`
+    pub fn clear_crash_triggers(&mut self) {
+        self.crash_schedule = CrashSchedule::default();
     }
 }
@@ -1,16 +1,221 @@
-use wg_2024::packet::Packet;
+use std::fmt;
 
+use crossbeam_channel::Sender;
+use wg_2024::network::NodeId;
+use wg_2024::packet::{Packet, PacketType};
+
+use super::crashed::CrashTrigger;
 use super::FungiDrone;
 
+/// Why the debug system was called, for the edge cases which aren't covered by the protocol
+#[derive(Debug, Clone, PartialEq)]
+pub enum DebugReason {
+    /// The hop index of a packet is beyond the length of its hops
+    HopIndexOutOfRange,
+    /// The next hop of a packet is not a neighbor of the drone
+    NextHopNotNeighbor(NodeId),
+    /// The channel of a neighbor is full, the packet waits to be retried
+    ChannelFull(NodeId),
+    /// A neighbor's channel stayed full, the drone gave up on the packet
+    GaveUp,
+    /// All senders of the drone's receiver are gone, but the drone hasn't crashed
+    NoSenders,
+    /// The simulation controller no longer listens to the drone
+    ControllerGone,
+    /// Nobody listens to the fault channel anymore
+    FaultChannelGone,
+    /// The packet capture couldn't be written, capturing stopped
+    CaptureFailed(String),
+    /// A FloodRequest without a path trace, the drone can't tell who sent it
+    MalformedFlood { flood_id: u64, initiator_id: NodeId },
+    /// A FloodRequest whose last hop is not a neighbor of the drone
+    UnknownPreviousHop {
+        flood_id: u64,
+        initiator_id: NodeId,
+        previous_hop: NodeId,
+    },
+    /// A FloodResponse can't be sent back to a neighbor, it goes to the simulation controller
+    UnroutableFloodResponse,
+    /// A flood of `initiator_id` is over the initiator's rate limit
+    FloodThrottled { flood_id: u64, initiator_id: NodeId },
+    /// One of the crash triggers fired
+    ScheduledCrash(CrashTrigger),
+    /// The drone came back online
+    Recovered,
+    /// The crash drain stopped at its deadline while some senders may still be held
+    DrainTimedOut {
+        neighbors: Vec<NodeId>,
+        heard_from: Vec<NodeId>,
+    },
+    /// Fault injection forwarded the packet twice
+    Duplicated,
+    /// Fault injection corrupted the fragment
+    Corrupted,
+    /// A byzantine drone dropped the packet without telling anyone
+    Swallowed,
+    /// A byzantine drone sent the packet to the wrong neighbor
+    Misrouted { next_hop: NodeId, sent_to: NodeId },
+}
+
+/// Groups of [`DebugReason`], for showing diagnostics by kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DebugCategory {
+    /// The routing header of a packet can't be followed
+    Routing,
+    /// Something went wrong on a link with a neighbor
+    Link,
+    /// The drone lost one of its outputs: the simulation controller, the fault channel or the capture
+    Controller,
+    /// A FloodRequest or FloodResponse couldn't be handled as usual
+    Flooding,
+    /// The drone crashed or recovered
+    Crash,
+    /// A fault was injected on purpose
+    Fault,
+}
+
+impl DebugReason {
+    pub fn category(&self) -> DebugCategory {
+        match self {
+            DebugReason::HopIndexOutOfRange | DebugReason::NextHopNotNeighbor(_) => {
+                DebugCategory::Routing
+            }
+            DebugReason::ChannelFull(_) | DebugReason::GaveUp | DebugReason::NoSenders => {
+                DebugCategory::Link
+            }
+            DebugReason::ControllerGone
+            | DebugReason::FaultChannelGone
+            | DebugReason::CaptureFailed(_) => DebugCategory::Controller,
+            DebugReason::MalformedFlood { .. }
+            | DebugReason::UnknownPreviousHop { .. }
+            | DebugReason::UnroutableFloodResponse
+            | DebugReason::FloodThrottled { .. } => DebugCategory::Flooding,
+            DebugReason::ScheduledCrash(_)
+            | DebugReason::Recovered
+            | DebugReason::DrainTimedOut { .. } => DebugCategory::Crash,
+            DebugReason::Duplicated
+            | DebugReason::Corrupted
+            | DebugReason::Swallowed
+            | DebugReason::Misrouted { .. } => DebugCategory::Fault,
+        }
+    }
+}
+
+impl fmt::Display for DebugReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DebugReason::HopIndexOutOfRange => write!(f, "The hop index is out of bounds"),
+            DebugReason::NextHopNotNeighbor(id) => write!(f, "The next hop {id} is not a neighbor"),
+            DebugReason::ChannelFull(id) => write!(f, "The channel of {id} is full"),
+            DebugReason::GaveUp => write!(f, "The next node's channel is full, giving up on the packet"),
+            DebugReason::NoSenders => write!(f, "No senders, but not in crash behaviour"),
+            DebugReason::ControllerGone => write!(f, "No longer has access to the simulation controller"),
+            DebugReason::FaultChannelGone => write!(f, "No longer has access to the fault channel"),
+            DebugReason::CaptureFailed(error) => write!(f, "The capture can't be written: {error}"),
+            DebugReason::MalformedFlood {
+                flood_id,
+                initiator_id,
+            } => write!(f, "Flood request {flood_id} of {initiator_id} has an empty path trace"),
+            DebugReason::UnknownPreviousHop {
+                flood_id,
+                initiator_id,
+                previous_hop,
+            } => write!(
+                f,
+                "Flood request {flood_id} of {initiator_id} comes from {previous_hop}, which is not a neighbor"
+            ),
+            DebugReason::UnroutableFloodResponse => {
+                write!(f, "The flood response can't be sent to a neighbor")
+            }
+            DebugReason::FloodThrottled {
+                flood_id,
+                initiator_id,
+            } => write!(f, "Flood request {flood_id} of {initiator_id} is over the rate limit"),
+            DebugReason::ScheduledCrash(trigger) => write!(f, "Scheduled crash: {trigger:?}"),
+            DebugReason::Recovered => write!(f, "Recovered from crash"),
+            DebugReason::DrainTimedOut {
+                neighbors,
+                heard_from,
+            } => write!(
+                f,
+                "Crash drain timed out, senders may still be held by {neighbors:?}, heard from {heard_from:?}"
+            ),
+            DebugReason::Duplicated => write!(f, "Duplicating the packet"),
+            DebugReason::Corrupted => write!(f, "Corrupting the fragment"),
+            DebugReason::Swallowed => write!(f, "Byzantine: swallowing the packet"),
+            DebugReason::Misrouted { next_hop, sent_to } => {
+                write!(f, "Byzantine: misrouting the packet for {next_hop} to {sent_to}")
+            }
+        }
+    }
+}
+
+/// A diagnostic of a drone, as sent by the channel [`DebugSink`]
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub drone: NodeId,
+    pub reason: DebugReason,
+    /// The packet the diagnostic is about, if any
+    pub packet: Option<Packet>,
+}
+
+/// Receives every call to the debug system, next to the packet it is about
+pub trait DebugSink: fmt::Debug + Send {
+    fn report(&self, drone: NodeId, reason: &DebugReason, packet: Option<&Packet>);
+}
+
+/// Sends each diagnostic on the channel, they are lost if nobody listens anymore
+impl DebugSink for Sender<Diagnostic> {
+    fn report(&self, drone: NodeId, reason: &DebugReason, packet: Option<&Packet>) {
+        let _ = self.try_send(Diagnostic {
+            drone,
+            reason: reason.clone(),
+            packet: packet.cloned(),
+        });
+    }
+}
+
+/// Opens a `tracing` span around the handling of a packet.
+/// The span carries the drone id, session id, fragment index and hop index,
+/// so the logs of a packet can be followed across drones
+macro_rules! packet_span {
+    ($name:literal, $drone:expr, $packet:expr $(, $($field:tt)*)?) => {{
+        let packet: &wg_2024::packet::Packet = $packet;
+        tracing::debug_span!(
+            $name,
+            drone = $drone,
+            session_id = packet.session_id,
+            fragment_index = $crate::drone::debug::fragment_index(packet),
+            hop_index = packet.routing_header.hop_index,
+            $($($field)*)?
+        )
+    }};
+}
+pub(super) use packet_span;
+
+/// The fragment index of a packet, for the ones which have one
+pub(super) fn fragment_index(packet: &Packet) -> Option<u64> {
+    match &packet.pack_type {
+        PacketType::MsgFragment(fragment) => Some(fragment.fragment_index),
+        PacketType::Nack(nack) => Some(nack.fragment_index),
+        PacketType::Ack(ack) => Some(ack.fragment_index),
+        PacketType::FloodRequest(_) | PacketType::FloodResponse(_) => None,
+    }
+}
+
 impl FungiDrone {
-    pub(super) fn debug(&self, debug_message: &str, debug_packet: Option<Packet>) {
+    /// Calls the debug system: the reason is always emitted as a `tracing` event,
+    /// then printed, sent to the debug sink and shortcut as configured
+    pub(super) fn debug(&self, reason: DebugReason, debug_packet: Option<Packet>) {
+        tracing::warn!(drone = self.id, category = ?reason.category(), "{reason}");
         if self.debug_print {
-            println!("[{}]: {debug_message}", self.id);
+            println!("[{}]: {reason}", self.id);
         }
-        if self.debug_shortcut && debug_packet.is_some() {
-            self.send_controller(wg_2024::controller::DroneEvent::ControllerShortcut(
-                debug_packet.unwrap(),
-            ));
+        if let Some(sink) = &self.debug_sink {
+            sink.report(self.id, &reason, debug_packet.as_ref());
+        }
+        if let Some(packet) = debug_packet.filter(|_| self.debug_shortcut) {
+            self.send_controller(wg_2024::controller::DroneEvent::ControllerShortcut(packet));
         }
     }
 
@@ -21,4 +226,9 @@ impl FungiDrone {
     pub fn set_debug_shortcut(&mut self) {
         self.debug_shortcut = true;
     }
+
+    /// Sends every diagnostic of the drone to `sink`, with its reason and the packet it is about
+    pub fn set_debug_sink<S: DebugSink + 'static>(&mut self, sink: S) {
+        self.debug_sink = Some(Box::new(sink));
+    }
 }
@@ -1,17 +1,29 @@
+use super::byzantine::Byzantine;
+use super::capture::Capture;
+use super::check_packet::CheckPipeline;
+use super::debug::{packet_span, DebugReason, DebugSink};
+use super::crashed::{CrashSchedule, CrashTrigger, CrashedFloodPolicy, Recovery};
+use super::flood_memory::{FloodMemory, FloodMemoryPolicy};
+use super::faults::{Corruption, FaultEvent, Faults, ReorderWindow, Reordering};
 use super::helper::{generate, header};
-use crossbeam_channel::{select_biased, Receiver, RecvError, Sender, TrySendError};
-use rand::{Rng, RngCore};
+use super::loss::{Bernoulli, LossModel};
+use super::metrics::Metrics;
+use super::outbound::{Latency, Outbound, PriorityTable, RetryPolicy, Undelivered};
+use super::rate_limit::{FloodLimiter, FloodRateLimit};
+use crossbeam_channel::{at, never, select_biased, Receiver, RecvError, Sender, TrySendError};
+use rand::RngCore;
 use rand_xoshiro::rand_core::SeedableRng;
 use rand_xoshiro::Xoshiro256PlusPlus;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 use wg_2024::controller::{DroneCommand, DroneEvent};
 use wg_2024::drone::Drone;
 use wg_2024::network::NodeId;
-use wg_2024::packet::{NackType, Packet, PacketType};
+use wg_2024::packet::{Packet, PacketType};
 
 #[derive(Debug)]
 pub struct FungiDrone {
-    pub(super) seen_flood_ids: HashSet<(u64, u8)>, //Hashset<(FloodId, InitiatorId)>
+    pub(super) seen_flood_ids: FloodMemory,
     pub(super) id: NodeId,
     pub(super) controller_send: Sender<wg_2024::controller::DroneEvent>,
     pub(super) controller_recv: Receiver<DroneCommand>,
@@ -20,7 +32,70 @@ pub struct FungiDrone {
     pub(super) pdr: f32,
     pub(super) debug_print: bool,
     pub(super) debug_shortcut: bool,
+    pub(super) debug_sink: Option<Box<dyn DebugSink>>,
     pub(super) shortcut_requests: bool,
+    pub(super) seed: u64,
+    pub(super) rng: Xoshiro256PlusPlus,
+    pub(super) checks: CheckPipeline,
+    pub(super) loss_model: Box<dyn LossModel>,
+    pub(super) link_pdr: HashMap<NodeId, f32>,
+    pub(super) fungi_recv: Receiver<FungiCommand>,
+    pub(super) outbound: Outbound,
+    pub(super) faults: Faults,
+    pub(super) fault_send: Option<Sender<FaultEvent>>,
+    pub(super) reorder: ReorderWindow,
+    pub(super) byzantine: Option<Byzantine>,
+    pub(super) crash_schedule: CrashSchedule,
+    pub(super) recovery: Recovery,
+    pub(super) drain_timeout: Option<Duration>,
+    pub(super) crashed_flood_policy: CrashedFloodPolicy,
+    pub(super) flood_limiter: FloodLimiter,
+    pub(super) metrics: Metrics,
+    pub(super) capture: Option<Capture>,
+}
+
+/// Commands specific to the fungi drone, sent on the channel given to [`FungiDrone::set_command_recv`].
+/// They extend the protocol's `DroneCommand`s with the drone's own settings.
+#[derive(Debug)]
+pub enum FungiCommand {
+    /// Replaces the model deciding which fragments are dropped
+    SetLossModel(Box<dyn LossModel>),
+    /// Sets the drop rate of the link towards a neighbor
+    SetLinkDropRate(NodeId, f32),
+    /// Makes the link towards a neighbor use the drone-wide loss model again
+    ClearLinkDropRate(NodeId),
+    /// Sets the transmission delay of the link towards a neighbor
+    SetLinkLatency(NodeId, Latency),
+    /// Makes the link towards a neighbor send packets immediately again
+    ClearLinkLatency(NodeId),
+    /// Changes how long packets wait when the next drone's channel is full
+    SetRetryPolicy(RetryPolicy),
+    /// Changes which packets go first when they wait for a neighbor's channel
+    SetPriorityTable(PriorityTable),
+    /// Forwards every routed packet a second time with the given probability
+    SetDuplicateRate(f32),
+    /// Corrupts the fragments going through the drone, `None` to disable it
+    SetCorruption(Option<Corruption>),
+    /// Releases the forwarded packets out of order, `None` to disable it
+    SetReordering(Option<Reordering>),
+    /// Makes the drone misbehave on purpose, `None` to make it behave again
+    SetByzantine(Option<Byzantine>),
+    /// Makes the drone crash by itself when the trigger fires
+    AddCrashTrigger(CrashTrigger),
+    /// Removes every crash trigger
+    ClearCrashTriggers,
+    /// Brings a crashed drone back online, does nothing if the drone isn't crashed
+    Recover,
+    /// Sets how the drone comes back online after crashing
+    SetRecovery(Recovery),
+    /// Sets how long a crashed drone drains its channel, `None` to wait for all senders to be removed
+    SetDrainTimeout(Option<Duration>),
+    /// Sets what the drone does with the FloodRequests it receives while crashed
+    SetCrashedFloodPolicy(CrashedFloodPolicy),
+    /// Changes how the drone bounds the memory of the flood ids it has seen
+    SetFloodMemoryPolicy(FloodMemoryPolicy),
+    /// Limits the new floods each initiator can start through the drone, `None` to remove the limit
+    SetFloodRateLimit(Option<FloodRateLimit>),
 }
 
 pub(super) enum CommandResult {
@@ -38,6 +113,7 @@ impl Drone for FungiDrone {
         packet_send: HashMap<NodeId, Sender<Packet>>,
         pdr: f32,
     ) -> Self {
+        let seed = rand::thread_rng().next_u64();
         Self {
             id,
             controller_send,
@@ -45,32 +121,68 @@ impl Drone for FungiDrone {
             packet_recv,
             packet_send,
             pdr,
-            seen_flood_ids: HashSet::new(),
+            seen_flood_ids: FloodMemory::default(),
             debug_print: false,
             debug_shortcut: false,
+            debug_sink: None,
             shortcut_requests: true,
+            seed,
+            rng: Xoshiro256PlusPlus::seed_from_u64(seed),
+            checks: CheckPipeline::default(),
+            loss_model: Box::new(Bernoulli::new(pdr)),
+            link_pdr: HashMap::new(),
+            fungi_recv: never(),
+            outbound: Outbound::default(),
+            faults: Faults::default(),
+            fault_send: None,
+            reorder: ReorderWindow::default(),
+            byzantine: None,
+            crash_schedule: CrashSchedule::default(),
+            recovery: Recovery::default(),
+            drain_timeout: None,
+            crashed_flood_policy: CrashedFloodPolicy::default(),
+            flood_limiter: FloodLimiter::default(),
+            metrics: Metrics::new(id),
+            capture: None,
         }
     }
 
     fn run(&mut self) {
         loop {
+            let timer = self.timer();
             select_biased! {
                 recv(self.controller_recv) -> command_res => {
                     match self.handle_command_internal(command_res){
-                        CommandResult::NoController => self.debug("The simulation controller no longer has access to this drone",None),
-                        CommandResult::Continue => continue,
+                        CommandResult::NoController => self.debug(DebugReason::ControllerGone, None),
+                        CommandResult::Continue => self.tick_crash_schedule(false),
                         CommandResult::Break => break,
                     }
                 },
+                recv(self.fungi_recv) -> command_res => {
+                    if let Ok(command) = command_res {
+                        self.handle_fungi_command(command);
+                        self.tick_crash_schedule(false);
+                    } else {
+                        self.fungi_recv = never();
+                    }
+                },
+                // Timed work goes before packets, so that a busy link can't hold it back
+                recv(timer) -> _ => self.on_tick(),
                 recv(self.packet_recv) -> packet_res => {
                   if let Ok(msg) = packet_res {
                     self.handle_packet_internal(msg);
+                    self.tick_crash_schedule(true);
                   } else {
-                    self.debug("No senders, but not in crash behaviour",None);
+                    self.debug(DebugReason::NoSenders, None);
                   }
-                }
+                },
+            }
+
+            if self.scheduled_crash_due() && !self.crash_behaviour() {
+                break;
             }
         }
+        self.flush_capture();
     }
 }
 
@@ -82,26 +194,31 @@ impl FungiDrone {
     ///
     /// ## Returns
     /// The action which the drone should do after having dealt with the command
-    fn handle_command_internal(
+    pub(super) fn handle_command_internal(
         &mut self,
         command_res: Result<DroneCommand, RecvError>,
     ) -> CommandResult {
         if let Ok(msg) = command_res {
+            self.capture_command(&msg);
             match msg {
                 DroneCommand::AddSender(node_id, channel_sender) => {
                     self.packet_send.insert(node_id, channel_sender);
                     return CommandResult::Continue;
                 }
                 DroneCommand::Crash => {
-                    self.crash_behaviour();
+                    if self.crash_behaviour() {
+                        return CommandResult::Continue;
+                    }
                     return CommandResult::Break;
                 }
                 DroneCommand::SetPacketDropRate(pdr) => {
                     self.pdr = pdr;
+                    self.loss_model = Box::new(Bernoulli::new(pdr));
                     return CommandResult::Continue;
                 }
                 DroneCommand::RemoveSender(node_id) => {
                     self.packet_send.remove(&node_id);
+                    self.purge_neighbor(node_id);
                     return CommandResult::Continue;
                 }
             }
@@ -109,10 +226,44 @@ impl FungiDrone {
         CommandResult::NoController
     }
 
+    /// Applies a command received on the fungi command channel
+    ///
+    /// ## Arguments
+    /// - `command`: The command to apply
+    pub(super) fn handle_fungi_command(&mut self, command: FungiCommand) {
+        match command {
+            FungiCommand::SetLossModel(model) => self.loss_model = model,
+            FungiCommand::SetLinkDropRate(neighbor, pdr) => self.set_link_drop_rate(neighbor, pdr),
+            FungiCommand::ClearLinkDropRate(neighbor) => self.clear_link_drop_rate(neighbor),
+            FungiCommand::SetLinkLatency(neighbor, latency) => self.set_link_latency(neighbor, latency),
+            FungiCommand::ClearLinkLatency(neighbor) => self.clear_link_latency(neighbor),
+            FungiCommand::SetRetryPolicy(policy) => self.set_retry_policy(policy),
+            FungiCommand::SetPriorityTable(table) => self.set_priority_table(table),
+            FungiCommand::SetDuplicateRate(rate) => self.set_duplicate_rate(rate),
+            FungiCommand::SetCorruption(corruption) => self.set_corruption(corruption),
+            FungiCommand::SetReordering(reordering) => self.set_reordering(reordering),
+            FungiCommand::SetByzantine(byzantine) => self.set_byzantine(byzantine),
+            FungiCommand::AddCrashTrigger(trigger) => self.add_crash_trigger(trigger),
+            FungiCommand::ClearCrashTriggers => self.clear_crash_triggers(),
+            FungiCommand::Recover => (),
+            FungiCommand::SetRecovery(recovery) => self.set_recovery(recovery),
+            FungiCommand::SetDrainTimeout(timeout) => self.set_drain_timeout(timeout),
+            FungiCommand::SetCrashedFloodPolicy(policy) => self.set_crashed_flood_policy(policy),
+            FungiCommand::SetFloodMemoryPolicy(policy) => self.set_flood_memory_policy(policy),
+            FungiCommand::SetFloodRateLimit(limit) => self.set_flood_rate_limit(limit),
+        }
+    }
+
     /// Controls the receiving of a Packet
     /// ## Arguments
     /// - `packet_res`: The result of listening to the drone's own Receiver<Packet> , it contains the Packet received
-    fn handle_packet_internal(&mut self, msg: Packet) {
+    pub(super) fn handle_packet_internal(&mut self, msg: Packet) {
+        let span = packet_span!("packet", self.id, &msg);
+        let _enter = span.enter();
+        tracing::trace!("received");
+        self.metrics.count_received(&msg);
+        self.capture_received(&msg);
+
         if let PacketType::FloodRequest(flood_request) = msg.pack_type {
             self.receive_flood_request(flood_request, msg.session_id);
         } else {
@@ -126,19 +277,145 @@ impl FungiDrone {
 
                 let (pack_ready, id, sender) = sender_res.unwrap();
 
+                let Some((pack_ready, id, sender)) = self.apply_byzantine(pack_ready, id, sender)
+                else {
+                    return;
+                };
+
                 self.log_action(pack_ready.clone(), false);
-                self.forward(pack_ready, id, sender);
+                self.forward_faulty(pack_ready, id, sender);
+            }
+        }
+    }
+
+    /// Returns a channel which fires when the drone has timed work to do,
+    /// or never if there is none
+    pub(super) fn timer(&self) -> Receiver<Instant> {
+        let deadline = [
+            self.outbound.next_due(),
+            self.outbound.next_retry(),
+            self.reorder.next_release(),
+            self.crash_schedule.deadline(),
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+
+        match deadline {
+            Some(deadline) => at(deadline),
+            None => never(),
+        }
+    }
+
+    /// Does all the timed work which is due
+    pub(super) fn on_tick(&mut self) {
+        let now = Instant::now();
+
+        self.release_due(now);
+
+        let undelivered = self.outbound.retry(now);
+        self.report_undelivered(undelivered);
+
+        while let Some(delayed) = self.outbound.pop_due(now) {
+            self.transmit(delayed.packet, delayed.next_id, delayed.sender);
+        }
+    }
+
+    /// Sends every packet still waiting in the outbound queues, without waiting for them to be due.
+    /// Packets which still don't fit in the next drone's channel are given up.
+    pub(super) fn flush_outbound(&mut self) {
+        for delayed in self.outbound.drain() {
+            self.transmit(delayed.packet, delayed.next_id, delayed.sender);
+        }
+
+        let undelivered = self.outbound.drain_pending();
+        self.report_undelivered(undelivered);
+    }
+
+    /// Reports the packets still waiting to be sent to a removed neighbor as if it had disconnected,
+    /// so that none of them reaches it after its removal
+    fn purge_neighbor(&mut self, next_id: u8) {
+        let held = self.reorder.take_neighbor(next_id);
+        let waiting = self.outbound.remove_neighbor(next_id);
+        for packet in held.into_iter().map(|held| held.packet).chain(waiting) {
+            self.handle_disconnected(packet, next_id);
+        }
+    }
+
+    /// Reports the packets which left the outbound queues without being sent
+    fn report_undelivered(&mut self, undelivered: Undelivered) {
+        for packet in undelivered.gave_up {
+            self.give_up(packet);
+        }
+        for (packet, next_id) in undelivered.disconnected {
+            self.handle_disconnected(packet, next_id);
+        }
+    }
+
+    /// Reports a packet which could not be delivered to the next node because its channel stayed full:
+    /// - MsgFragments: are logged as dropped, and a Dropped Nack is sent back to the source
+    /// - FloodRequests: are logged as dropped
+    /// - Nack/Ack/FloodResponse: are sent to the simulation controller, since they can't be lost
+    fn give_up(&mut self, packet: Packet) {
+        self.debug(DebugReason::GaveUp, None);
+        match &packet.pack_type {
+            PacketType::MsgFragment(fragment) => {
+                let nack = generate::dropped_packet(
+                    packet.routing_header.clone(),
+                    packet.session_id,
+                    fragment.clone(),
+                );
+                self.log_action(packet, true);
+
+                self.metrics.count_nack(&nack);
+                if let Some((nack, nack_id, nack_sender)) = self.get_send_info(nack) {
+                    self.forward(nack, nack_id, nack_sender);
+                }
+            }
+            PacketType::FloodRequest(_) => self.log_action(packet, true),
+            PacketType::Ack(_) | PacketType::Nack(_) | PacketType::FloodResponse(_) => {
+                self.send_controller(DroneEvent::ControllerShortcut(packet))
             }
         }
     }
 
-    /// Forwards a packet to the next drone
+    /// Forwards a packet to the next drone,
+    /// holding it back first if the link towards the next drone has a latency
     ///
     /// ## Arguments
     /// - `p`: Packet to be forwarded
     /// - `next_id`: The idea of the drone to which the packet should be sent
     /// - `p_sender`: The sender of the next drone's channel
     pub(super) fn forward(&mut self, p: Packet, next_id: u8, p_sender: Sender<Packet>) {
+        let span = packet_span!("forward", self.id, &p, next_hop = next_id);
+        let _enter = span.enter();
+        self.crash_schedule.count_forwarded();
+        self.metrics.count_forwarded(&p, next_id);
+        self.capture_forwarded(&p, next_id);
+
+        if let Some(latency) = self.outbound.latency(next_id) {
+            let delay = latency.sample(&mut self.rng);
+            tracing::trace!(?delay, "delayed");
+            self.outbound.delay(p, next_id, p_sender, delay);
+            return;
+        }
+
+        tracing::trace!("sent");
+        self.transmit(p, next_id, p_sender);
+    }
+
+    /// Hands a packet to the next drone's channel
+    ///
+    /// ## Arguments
+    /// - `p`: Packet to be sent
+    /// - `next_id`: The id of the drone to which the packet should be sent
+    /// - `p_sender`: The sender of the next drone's channel
+    pub(super) fn transmit(&mut self, p: Packet, next_id: u8, p_sender: Sender<Packet>) {
+        if self.outbound.is_backlogged(next_id) {
+            self.queue_full(p, next_id, p_sender);
+            return;
+        }
+
         let res = p_sender.try_send(p);
 
         if res.is_ok() {
@@ -147,15 +424,33 @@ impl FungiDrone {
 
         match res.unwrap_err() {
             TrySendError::Full(msg) => {
-                self.debug("The next node's channel is full", Some(msg));
+                self.debug(DebugReason::ChannelFull(next_id), None);
+                self.queue_full(msg, next_id, p_sender);
             }
-            TrySendError::Disconnected(msg) => match &msg.pack_type {
-                PacketType::MsgFragment(_) => self.handle_send_error(msg, next_id),
-                PacketType::Ack(_) | PacketType::Nack(_) | PacketType::FloodResponse(_) => {
-                    self.send_controller(DroneEvent::ControllerShortcut(msg))
-                }
-                PacketType::FloodRequest(_) => (),
-            },
+            TrySendError::Disconnected(msg) => self.handle_disconnected(msg, next_id),
+        }
+    }
+
+    /// Queues a packet for the next node whose channel is full.
+    /// If the queue is full as well, either the packet or a lower priority one is given up.
+    fn queue_full(&mut self, p: Packet, next_id: u8, p_sender: Sender<Packet>) {
+        if let Some(given_up) = self.outbound.queue(p, next_id, p_sender) {
+            self.give_up(given_up);
+        }
+    }
+
+    /// Handles a packet whose next node's channel has been closed
+    ///
+    /// ## Arguments
+    /// - `msg`: Packet which could not be sent
+    /// - `next_id`: The id of the node which is no longer reachable
+    fn handle_disconnected(&mut self, msg: Packet, next_id: u8) {
+        match &msg.pack_type {
+            PacketType::MsgFragment(_) => self.handle_send_error(msg, next_id),
+            PacketType::Ack(_) | PacketType::Nack(_) | PacketType::FloodResponse(_) => {
+                self.send_controller(DroneEvent::ControllerShortcut(msg))
+            }
+            PacketType::FloodRequest(_) => (),
         }
     }
 
@@ -172,6 +467,7 @@ impl FungiDrone {
             let err_p =
                 generate::route_error(p.routing_header, p.session_id, next_id, f.fragment_index);
 
+            self.metrics.count_nack(&err_p);
             if let Some((err_p, err_id, err_sender)) = self.get_send_info(err_p) {
                 // fine to pass ownership
                 self.forward(err_p, err_id, err_sender);
@@ -187,7 +483,7 @@ impl FungiDrone {
         let hop_id = header::get_hop(&p.routing_header);
 
         if hop_id.is_none() {
-            self.debug("The hop index is out of bounds", Some(p));
+            self.debug(DebugReason::HopIndexOutOfRange, Some(p));
             return None;
         }
 
@@ -195,20 +491,22 @@ impl FungiDrone {
         let sender_res = self.packet_send.get(&id);
 
         if sender_res.is_none() {
-            self.debug("The sender is not in the hashmap", Some(p));
+            self.debug(DebugReason::NextHopNotNeighbor(id), Some(p));
             return None;
         }
 
         return Some((p, id, self.packet_send.get(&id).unwrap().clone()));
     }
 
-    /// Decides if a package should be dropped according to the probability in [`DroneOptions::pdr`].
-    /// The random number is generated using `Xoshiro 256++`, a library used to generate more unpredictable pseudo-random numbers.
-    pub(super) fn dropped(&self) -> bool {
-        let xoshiro_seed = rand::thread_rng().next_u64();
-        let mut xoshiro = Xoshiro256PlusPlus::seed_from_u64(xoshiro_seed);
-        let random_value = xoshiro.gen_range(0.0..1.0);
-        random_value < self.pdr as f64
+    /// Decides if a package should be dropped on its way to `next_id`.
+    /// Links with their own drop rate roll against it, the others use the drone's [`LossModel`].
+    /// The random numbers are drawn from the drone's own `Xoshiro 256++` generator,
+    /// so the same seed always yields the same sequence of drops.
+    pub(super) fn dropped(&mut self, next_id: NodeId) -> bool {
+        match self.link_pdr.get(&next_id) {
+            Some(&pdr) => Bernoulli::new(pdr).dropped(&mut self.rng),
+            None => self.loss_model.dropped(&mut self.rng),
+        }
     }
 
     /// Decides whether to send a packet dropped or packet sent event to the controller
@@ -218,6 +516,11 @@ impl FungiDrone {
     /// -`packet`: The packet to be sent in the event
     /// -`dropped`: Whether or not the packet has been dropped
     pub(super) fn log_action(&self, packet: Packet, dropped: bool) {
+        if dropped {
+            tracing::debug!(drone = self.id, "dropped");
+            self.metrics.count_dropped();
+        }
+
         if matches!(&packet.pack_type, PacketType::FloodRequest(_)) && !self.shortcut_requests {
           return;
         };
@@ -234,13 +537,82 @@ impl FungiDrone {
     /// ## Arguments
     /// - `event`: The event object to be sent
     pub(super) fn send_controller(&self, event: DroneEvent) {
+        if matches!(event, DroneEvent::ControllerShortcut(_)) {
+            self.metrics.count_shortcut();
+        }
         let res = self.controller_send.try_send(event);
         if res.is_err() {
-            self.debug("no longer has access to simulation controller!", None);
+            self.debug(DebugReason::ControllerGone, None);
         }
     }
 
     pub fn disable_request_log(&mut self){
       self.shortcut_requests = false;
     }
+
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
+
+    /// Replaces the model deciding which fragments are dropped.
+    /// A `SetPacketDropRate` command switches back to a [`Bernoulli`] model with the new rate.
+    pub fn set_loss_model<L: LossModel + 'static>(&mut self, model: L) {
+        self.loss_model = Box::new(model);
+    }
+
+    /// Gives the link towards `neighbor` its own drop rate, overriding the drone-wide loss model.
+    /// The rate is kept if the neighbor is removed and added back.
+    pub fn set_link_drop_rate(&mut self, neighbor: NodeId, pdr: f32) {
+        self.link_pdr.insert(neighbor, pdr);
+    }
+
+    /// Makes the link towards `neighbor` fall back to the drone-wide loss model
+    pub fn clear_link_drop_rate(&mut self, neighbor: NodeId) {
+        self.link_pdr.remove(&neighbor);
+    }
+
+    /// Delays every packet sent to `neighbor`, Nacks and flooding included
+    pub fn set_link_latency(&mut self, neighbor: NodeId, latency: Latency) {
+        self.outbound.set_latency(neighbor, latency);
+    }
+
+    /// Makes packets to `neighbor` be sent immediately again.
+    /// Packets already waiting keep their delay.
+    pub fn clear_link_latency(&mut self, neighbor: NodeId) {
+        self.outbound.clear_latency(neighbor);
+    }
+
+    /// Changes how long packets wait when the next drone's channel is full,
+    /// and when they are given up
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.outbound.set_retry_policy(policy);
+    }
+
+    /// Changes which packets go first when they wait for a neighbor's channel.
+    /// By default Acks, Nacks and FloodResponses go ahead of fragments.
+    pub fn set_priority_table(&mut self, table: PriorityTable) {
+        self.outbound.set_priorities(table);
+    }
+
+    /// Sets the channel on which the drone listens for [`FungiCommand`]s while running
+    pub fn set_command_recv(&mut self, command_recv: Receiver<FungiCommand>) {
+        self.fungi_recv = command_recv;
+    }
+
+    /// Reseeds the drone's random number generator.
+    /// Two drones given the same seed and the same inputs drop the same packets.
+    ///
+    /// The generator is owned by the drone, so it survives `SetPacketDropRate`
+    /// and keeps its state while the drone is in crash behaviour.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = Xoshiro256PlusPlus::seed_from_u64(seed);
+        self.capture_reseed(seed);
+    }
+
+    /// Returns the seed the drone's random number generator was last seeded with.
+    /// Useful to replay a run: read it back and pass it to [`FungiDrone::set_seed`].
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
 }
@@ -0,0 +1,286 @@
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::Sender;
+use rand::Rng;
+use wg_2024::network::NodeId;
+use wg_2024::packet::{Fragment, Packet, PacketType};
+
+use super::crashed::{CrashTrigger, DrainSummary};
+use super::debug::DebugReason;
+use super::outbound::Latency;
+use super::rate_limit::ThrottleAction;
+use super::FungiDrone;
+
+/// Faults injected by the drone in the packets it forwards.
+/// Every fault is disabled by default.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub(super) struct Faults {
+    pub(super) duplicate_rate: f32,
+    pub(super) corruption: Option<Corruption>,
+}
+
+/// Corruption of the `MsgFragment`s going through the drone
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Corruption {
+    /// Probability of a fragment being corrupted
+    pub rate: f32,
+    /// Probability of each bit of a corrupted fragment's data being flipped, among the first `length` bytes
+    pub bit_error_rate: f32,
+    /// Probability of a corrupted fragment also getting a wrong `length`
+    pub length_rate: f32,
+}
+
+/// Reordering of the packets forwarded by the drone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reordering {
+    /// Packets held at the same time.
+    /// When the window is full, a random packet among the held ones and the new one is released.
+    pub window: usize,
+    /// Each packet is held for a random time up to `jitter`, and is released once it has passed.
+    /// This bounds the time any packet, Acks and Nacks included, can stay in the window.
+    /// Times too long to be represented, with a jitter such as `Duration::MAX`, have no bound:
+    /// the packet leaves when the window is full, disabled or the drone crashes.
+    pub jitter: Duration,
+}
+
+/// A packet held by the reordering window
+#[derive(Debug)]
+pub(super) struct HeldPacket {
+    /// `None` if the jitter drawn is too long to be represented, the packet then waits for a draw or a flush
+    release_at: Option<Instant>,
+    pub(super) packet: Packet,
+    pub(super) next_id: NodeId,
+    pub(super) sender: Sender<Packet>,
+}
+
+/// The packets held back to be released out of order
+#[derive(Debug, Default)]
+pub(super) struct ReorderWindow {
+    config: Option<Reordering>,
+    held: Vec<HeldPacket>,
+}
+
+impl ReorderWindow {
+    /// The instant at which the next held packet must be released
+    pub(super) fn next_release(&self) -> Option<Instant> {
+        self.held.iter().filter_map(|held| held.release_at).min()
+    }
+
+    /// Takes the held packets whose jitter has passed at `now`, in the order they are due
+    pub(super) fn take_due(&mut self, now: Instant) -> Vec<HeldPacket> {
+        let (mut due, held): (Vec<_>, Vec<_>) = std::mem::take(&mut self.held)
+            .into_iter()
+            .partition(|held| matches!(held.release_at, Some(release_at) if release_at <= now));
+        self.held = held;
+        due.sort_by_key(|held| held.release_at);
+        due
+    }
+
+    /// Takes the held packets for a neighbor which has been removed, in the order they are due
+    pub(super) fn take_neighbor(&mut self, next_id: NodeId) -> Vec<HeldPacket> {
+        let (mut removed, held): (Vec<_>, Vec<_>) = std::mem::take(&mut self.held)
+            .into_iter()
+            .partition(|held| held.next_id == next_id);
+        self.held = held;
+        removed.sort_by_key(|held| (held.release_at.is_none(), held.release_at));
+        removed
+    }
+
+    /// Takes every held packet
+    pub(super) fn take_all(&mut self) -> Vec<HeldPacket> {
+        std::mem::take(&mut self.held)
+    }
+}
+
+/// An action taken by the drone's fault injection,
+/// sent on the channel given to [`FungiDrone::set_fault_send`].
+///
+/// The channel also carries notices which aren't injected faults but changes of the drone's state:
+/// [`ScheduledCrash`](FaultEvent::ScheduledCrash), [`Recovered`](FaultEvent::Recovered),
+/// [`Drained`](FaultEvent::Drained) and [`Throttled`](FaultEvent::Throttled).
+#[derive(Debug, Clone)]
+pub enum FaultEvent {
+    /// `packet` has been forwarded twice by drone `drone`
+    Duplicated { drone: NodeId, packet: Packet },
+    /// Drone `drone` corrupted the fragment `fragment_index` of session `session_id`.
+    /// `flipped_bits` are the positions of the flipped bits in `Fragment.data`,
+    /// `length` is the original length if it was changed.
+    Corrupted {
+        drone: NodeId,
+        session_id: u64,
+        fragment_index: u64,
+        flipped_bits: Vec<usize>,
+        length: Option<u8>,
+    },
+    /// Drone `drone` dropped `packet` on purpose, without a Nack
+    Swallowed { drone: NodeId, packet: Packet },
+    /// Drone `drone` sent `packet` to `sent_to` instead of `next_hop`
+    Misrouted {
+        drone: NodeId,
+        packet: Packet,
+        next_hop: NodeId,
+        sent_to: NodeId,
+    },
+    /// Drone `drone` crashed by itself because `trigger` fired
+    ScheduledCrash { drone: NodeId, trigger: CrashTrigger },
+    /// Drone `drone` came back online after crashing
+    Recovered { drone: NodeId },
+    /// Drone `drone` stopped draining its channel after crashing.
+    /// The summary isn't reported anywhere else, without a fault channel it is lost
+    Drained { drone: NodeId, summary: DrainSummary },
+    /// Drone `drone` throttled a flood of `initiator_id` which was over its rate limit
+    Throttled {
+        drone: NodeId,
+        initiator_id: NodeId,
+        flood_id: u64,
+        action: ThrottleAction,
+    },
+}
+
+impl FungiDrone {
+    /// Forwards a packet which passed the checks, injecting the enabled faults
+    ///
+    /// ## Arguments
+    /// - `p`: Packet to be forwarded
+    /// - `next_id`: The id of the drone to which the packet should be sent
+    /// - `p_sender`: The sender of the next drone's channel
+    pub(super) fn forward_faulty(&mut self, mut p: Packet, next_id: u8, p_sender: Sender<Packet>) {
+        if let Some(corruption) = self.faults.corruption {
+            if let PacketType::MsgFragment(fragment) = &mut p.pack_type {
+                if self.roll(corruption.rate) {
+                    self.corrupt(fragment, p.session_id, corruption);
+                }
+            }
+        }
+
+        if self.roll(self.faults.duplicate_rate) {
+            self.debug(DebugReason::Duplicated, None);
+            self.report_fault(FaultEvent::Duplicated {
+                drone: self.id,
+                packet: p.clone(),
+            });
+            self.log_action(p.clone(), false);
+            self.forward_reordered(p.clone(), next_id, p_sender.clone());
+        }
+
+        self.forward_reordered(p, next_id, p_sender);
+    }
+
+    /// Forwards a packet through the reordering window, if there is one
+    fn forward_reordered(&mut self, p: Packet, next_id: u8, p_sender: Sender<Packet>) {
+        let Some(reordering) = self.reorder.config else {
+            self.forward(p, next_id, p_sender);
+            return;
+        };
+
+        let jitter = Latency::Uniform {
+            min: Duration::ZERO,
+            max: reordering.jitter,
+        };
+        let mut held = HeldPacket {
+            release_at: Instant::now().checked_add(jitter.sample(&mut self.rng)),
+            packet: p,
+            next_id,
+            sender: p_sender,
+        };
+
+        if self.reorder.held.len() >= reordering.window {
+            // The new packet takes part in the draw, so a full window can also let it straight through
+            let released = self.rng.gen_range(0..=self.reorder.held.len());
+            if let Some(swapped) = self.reorder.held.get_mut(released) {
+                std::mem::swap(swapped, &mut held);
+            }
+            self.forward(held.packet, held.next_id, held.sender);
+            return;
+        }
+
+        self.reorder.held.push(held);
+    }
+
+    /// Forwards the held packets whose jitter has passed
+    pub(super) fn release_due(&mut self, now: Instant) {
+        for held in self.reorder.take_due(now) {
+            self.forward(held.packet, held.next_id, held.sender);
+        }
+    }
+
+    /// Forwards every held packet at once, in random order
+    pub(super) fn flush_reorder_window(&mut self) {
+        let mut held = self.reorder.take_all();
+        while !held.is_empty() {
+            let released = held.swap_remove(self.rng.gen_range(0..held.len()));
+            self.forward(released.packet, released.next_id, released.sender);
+        }
+    }
+
+    /// Flips bits of a fragment's data and possibly changes its length, then reports it
+    fn corrupt(&mut self, fragment: &mut Fragment, session_id: u64, corruption: Corruption) {
+        // Only the bytes in use are corrupted, the padding after them is never read
+        let used = (fragment.length as usize).min(fragment.data.len());
+        let mut flipped_bits = Vec::new();
+        for bit in 0..used * 8 {
+            if self.roll(corruption.bit_error_rate) {
+                fragment.data[bit / 8] ^= 1 << (bit % 8);
+                flipped_bits.push(bit);
+            }
+        }
+
+        let mut length = None;
+        if self.roll(corruption.length_rate) {
+            length = Some(fragment.length);
+            let wrong_length = (fragment.length as usize + self.rng.gen_range(1..fragment.data.len()))
+                % (fragment.data.len() + 1);
+            fragment.length = wrong_length as u8;
+        }
+
+        self.debug(DebugReason::Corrupted, None);
+        self.report_fault(FaultEvent::Corrupted {
+            drone: self.id,
+            session_id,
+            fragment_index: fragment.fragment_index,
+            flipped_bits,
+            length,
+        });
+    }
+
+    /// Rolls the drone's random number generator against `probability`.
+    /// A probability of zero doesn't consume any random number,
+    /// so disabled faults don't change the drop pattern of a seeded drone.
+    pub(super) fn roll(&mut self, probability: f32) -> bool {
+        probability > 0.0 && self.rng.gen_range(0.0..1.0) < probability as f64
+    }
+
+    /// Sends a fault event, if someone is listening for them
+    pub(super) fn report_fault(&self, event: FaultEvent) {
+        if let Some(fault_send) = &self.fault_send {
+            if fault_send.try_send(event).is_err() {
+                self.debug(DebugReason::FaultChannelGone, None);
+            }
+        }
+    }
+
+    /// Forwards every routed packet a second time with probability `rate`
+    pub fn set_duplicate_rate(&mut self, rate: f32) {
+        self.faults.duplicate_rate = rate;
+    }
+
+    /// Corrupts the data and length of the fragments going through the drone, `None` to disable it
+    pub fn set_corruption(&mut self, corruption: Option<Corruption>) {
+        self.faults.corruption = corruption;
+    }
+
+    /// Holds back the forwarded packets to release them out of order, `None` to disable it.
+    /// Disabling it releases the packets currently held.
+    pub fn set_reordering(&mut self, reordering: Option<Reordering>) {
+        self.reorder.config = reordering;
+        if reordering.is_none() {
+            self.flush_reorder_window();
+        }
+    }
+
+    /// Sets the channel on which the drone reports the faults it injects,
+    /// and its crashes, recoveries, drains and throttled floods
+    pub fn set_fault_send(&mut self, fault_send: Sender<FaultEvent>) {
+        self.fault_send = Some(fault_send);
+    }
+}
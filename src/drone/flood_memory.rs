@@ -0,0 +1,201 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use wg_2024::network::NodeId;
+
+/// How the drone bounds the memory of the flood ids it has seen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FloodMemoryPolicy {
+    /// Every flood id is remembered for the lifetime of the drone
+    #[default]
+    Unbounded,
+    /// A flood id is forgotten once it hasn't been seen for this long.
+    /// Floods still in progress keep being seen, so they are never forgotten.
+    /// A TTL shorter than [`MIN_FLOOD_TTL`] is raised to it.
+    Ttl(Duration),
+    /// At most this many flood ids are remembered, the least recently seen is forgotten first.
+    /// At least one flood id is always remembered.
+    Lru(usize),
+    /// Only the highest flood id of each initiator and the [`FLOOD_ID_WINDOW`] ids below it are remembered,
+    /// so overlapping floods which arrive out of order are still told apart.
+    /// Any id older than the window counts as seen. Expects initiators to increase their flood ids.
+    HighestPerInitiator,
+}
+
+/// The shortest TTL of [`FloodMemoryPolicy::Ttl`].
+/// A zero TTL would never report a duplicate, and floods would circulate forever in a cycle.
+pub const MIN_FLOOD_TTL: Duration = Duration::from_millis(100);
+
+impl FloodMemoryPolicy {
+    /// The policy with its bounds raised so that duplicates are still detected
+    pub(super) fn clamped(self) -> Self {
+        match self {
+            FloodMemoryPolicy::Ttl(ttl) => FloodMemoryPolicy::Ttl(ttl.max(MIN_FLOOD_TTL)),
+            FloodMemoryPolicy::Lru(capacity) => FloodMemoryPolicy::Lru(capacity.max(1)),
+            policy => policy,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Store {
+    Unbounded(HashSet<(u64, NodeId)>),
+    Ttl {
+        ttl: Duration,
+        last_seen: HashMap<(u64, NodeId), Instant>,
+        /// `None` if the TTL is too long for anything to ever be forgotten
+        next_purge: Option<Instant>,
+    },
+    Lru {
+        capacity: usize,
+        clock: u64,
+        stamps: HashMap<(u64, NodeId), u64>,
+        order: BTreeMap<u64, (u64, NodeId)>,
+    },
+    HighestPerInitiator(HashMap<NodeId, RecentIds>),
+}
+
+/// How many flood ids below the highest one are remembered for each initiator
+/// with [`FloodMemoryPolicy::HighestPerInitiator`]
+pub const FLOOD_ID_WINDOW: u64 = 64;
+
+/// The highest flood id of an initiator, and which of the ids below it have been seen
+#[derive(Debug)]
+struct RecentIds {
+    highest: u64,
+    /// Bit `i` is set if the id `highest - i` has been seen
+    seen: u64,
+}
+
+impl RecentIds {
+    fn new(flood_id: u64) -> Self {
+        Self {
+            highest: flood_id,
+            seen: 1,
+        }
+    }
+
+    /// Records an id, returns `true` if it had already been seen or is older than the window
+    fn check_and_record(&mut self, flood_id: u64) -> bool {
+        if flood_id > self.highest {
+            let shift = flood_id - self.highest;
+            self.seen = match shift < FLOOD_ID_WINDOW {
+                true => self.seen << shift | 1,
+                false => 1,
+            };
+            self.highest = flood_id;
+            return false;
+        }
+
+        let offset = self.highest - flood_id;
+        if offset >= FLOOD_ID_WINDOW {
+            return true;
+        }
+        let bit = 1 << offset;
+        let seen = self.seen & bit != 0;
+        self.seen |= bit;
+        seen
+    }
+}
+
+/// The flood ids seen by the drone, as (FloodId, InitiatorId)
+#[derive(Debug)]
+pub(super) struct FloodMemory {
+    policy: FloodMemoryPolicy,
+    store: Store,
+}
+
+impl Default for FloodMemory {
+    fn default() -> Self {
+        Self::new(FloodMemoryPolicy::default())
+    }
+}
+
+impl FloodMemory {
+    pub(super) fn new(policy: FloodMemoryPolicy) -> Self {
+        let store = match policy {
+            FloodMemoryPolicy::Unbounded => Store::Unbounded(HashSet::new()),
+            FloodMemoryPolicy::Ttl(ttl) => Store::Ttl {
+                ttl,
+                last_seen: HashMap::new(),
+                next_purge: Instant::now().checked_add(ttl),
+            },
+            FloodMemoryPolicy::Lru(capacity) => Store::Lru {
+                capacity,
+                clock: 0,
+                stamps: HashMap::new(),
+                order: BTreeMap::new(),
+            },
+            FloodMemoryPolicy::HighestPerInitiator => Store::HighestPerInitiator(HashMap::new()),
+        };
+        Self { policy, store }
+    }
+
+    /// Records that a flood has been seen.
+    /// Returns `true` if it had already been seen before.
+    pub(super) fn check_and_record(&mut self, flood_id: u64, initiator_id: NodeId) -> bool {
+        let key = (flood_id, initiator_id);
+        match &mut self.store {
+            Store::Unbounded(seen) => !seen.insert(key),
+            Store::Ttl {
+                ttl,
+                last_seen,
+                next_purge,
+            } => {
+                let now = Instant::now();
+                if matches!(*next_purge, Some(purge_at) if now >= purge_at) {
+                    last_seen.retain(|_, seen_at| now.duration_since(*seen_at) < *ttl);
+                    *next_purge = now.checked_add(*ttl);
+                }
+
+                match last_seen.insert(key, now) {
+                    Some(seen_at) => now.duration_since(seen_at) < *ttl,
+                    None => false,
+                }
+            }
+            Store::Lru {
+                capacity,
+                clock,
+                stamps,
+                order,
+            } => {
+                *clock += 1;
+                let seen = match stamps.insert(key, *clock) {
+                    Some(stamp) => order.remove(&stamp).is_some(),
+                    None => false,
+                };
+                order.insert(*clock, key);
+
+                while stamps.len() > *capacity {
+                    let Some((_, oldest)) = order.pop_first() else {
+                        break;
+                    };
+                    stamps.remove(&oldest);
+                }
+                seen
+            }
+            Store::HighestPerInitiator(recent) => match recent.get_mut(&initiator_id) {
+                Some(ids) => ids.check_and_record(flood_id),
+                None => {
+                    recent.insert(initiator_id, RecentIds::new(flood_id));
+                    false
+                }
+            },
+        }
+    }
+
+    /// Forgets every flood seen, keeping the policy
+    pub(super) fn clear(&mut self) {
+        *self = Self::new(self.policy);
+    }
+
+    /// Number of entries currently remembered
+    pub(super) fn len(&self) -> usize {
+        match &self.store {
+            Store::Unbounded(seen) => seen.len(),
+            Store::Ttl { last_seen, .. } => last_seen.len(),
+            Store::Lru { stamps, .. } => stamps.len(),
+            Store::HighestPerInitiator(recent) => recent.len(),
+        }
+    }
+}
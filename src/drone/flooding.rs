@@ -1,33 +1,73 @@
-use wg_2024::network::SourceRoutingHeader;
+use crossbeam_channel::Sender;
+use wg_2024::controller::DroneEvent;
+use wg_2024::network::{NodeId, SourceRoutingHeader};
 use wg_2024::packet::{FloodRequest, NodeType, Packet, PacketType};
 
-use super::{generate, FungiDrone};
+use super::debug::DebugReason;
+use super::flood_memory::{FloodMemory, FloodMemoryPolicy};
+use super::{generate, header, FungiDrone};
+
+/// Why a FloodRequest couldn't be handled
+#[derive(Debug)]
+pub enum FloodError {
+    /// The path trace is empty, the drone can't tell who sent the request.
+    /// The request is discarded and the debug system is called
+    EmptyPathTrace { flood_id: u64, initiator_id: NodeId },
+    /// The last node of the path trace isn't a neighbor of the drone.
+    /// The request is discarded and the debug system is called
+    UnknownPreviousHop {
+        flood_id: u64,
+        initiator_id: NodeId,
+        previous_hop: NodeId,
+    },
+    /// The FloodResponse can't be sent back to a neighbor, it is delivered by the simulation controller
+    Unroutable(Box<Packet>),
+}
 
 impl FungiDrone {
-    /// Handles the flood request logic:
-    /// There are 3 paths:
+    /// Handles the flood request logic, any [`FloodError`] is routed through
+    /// the debug system or the simulation controller.
+    ///
+    /// There are 4 paths:
     /// - The drone has seen the flood id and sends back a flood response
+    /// - The drone has not seen the id but the initiator is over its rate limit, so the flood is throttled
     /// - The drone has not seen the id but has no neighbors so sends back a flood response
     /// - The drone has not seen the id and forwards it to all neighbors besides the previous sender
-    pub(super) fn receive_flood_request(&mut self, mut flood_req: FloodRequest, session_id: u64) {
-        // Check if the flood has been seen
+    pub(super) fn receive_flood_request(&mut self, flood_req: FloodRequest, session_id: u64) {
+        let span = tracing::debug_span!(
+            "flood",
+            drone = self.id,
+            session_id,
+            flood_id = flood_req.flood_id,
+            initiator_id = flood_req.initiator_id,
+        );
+        let _enter = span.enter();
+
+        if let Err(error) = self.flood(flood_req, session_id) {
+            self.handle_flood_error(error);
+        }
+    }
+
+    fn flood(&mut self, mut flood_req: FloodRequest, session_id: u64) -> Result<(), FloodError> {
+        // Requests which can't be answered are discarded before being remembered
+
+        let node_before = self.previous_hop(&flood_req)?;
+
+        // Check if the flood has been seen, it is remembered from now on
 
         if self
             .seen_flood_ids
-            .contains(&(flood_req.flood_id, flood_req.initiator_id))
+            .check_and_record(flood_req.flood_id, flood_req.initiator_id)
         {
+            tracing::debug!("already seen, answering");
             flood_req.path_trace.push((self.id, NodeType::Drone));
-            let response = generate::flood_response(self.id, flood_req, session_id);
-            let (response, next_id, p_sender) = self.get_send_info(response).unwrap();
-
-            self.forward(response, next_id, p_sender);
-            return;
+            return self.respond_to_flood(flood_req, session_id);
         }
 
-        self.seen_flood_ids
-            .insert((flood_req.flood_id, flood_req.initiator_id));
+        let Some(mut flood_req) = self.limit_flood(flood_req, session_id) else {
+            return Ok(());
+        };
 
-        let node_before = flood_req.path_trace.last().cloned().unwrap().0;
         flood_req.path_trace.push((self.id, NodeType::Drone));
 
         // Check if there are neighbors besides the one who sent the request
@@ -35,10 +75,8 @@ impl FungiDrone {
         // is when you have only 1 neighbor
 
         if self.packet_send.len() == 1 {
-            let response = generate::flood_response(self.id, flood_req, session_id);
-            let (response, next_id, p_sender) = self.get_send_info(response).unwrap();
-            self.forward(response, next_id, p_sender);
-            return;
+            tracing::debug!("no other neighbor, answering");
+            return self.respond_to_flood(flood_req, session_id);
         }
 
         let empty_header = SourceRoutingHeader {
@@ -52,10 +90,106 @@ impl FungiDrone {
             session_id,
         };
 
-        for (neighbor_id, sender) in self.packet_send.clone() {
-            if neighbor_id != node_before {
-                self.forward(request.clone(), neighbor_id, sender);
+        tracing::debug!(neighbors = self.packet_send.len() - 1, "flooding");
+        // Sorted so that seeded drones always flood their neighbors in the same order
+        let mut neighbors = self
+            .packet_send
+            .iter()
+            .filter(|(&id, _)| id != node_before)
+            .map(|(&id, sender)| (id, sender.clone()))
+            .collect::<Vec<(NodeId, Sender<Packet>)>>();
+        neighbors.sort_unstable_by_key(|(id, _)| *id);
+
+        for (neighbor_id, sender) in neighbors {
+            self.forward(request.clone(), neighbor_id, sender);
+        }
+        Ok(())
+    }
+
+    /// The neighbor which sent the request, the last node of its path trace
+    fn previous_hop(&self, flood_req: &FloodRequest) -> Result<NodeId, FloodError> {
+        let Some(&(previous_hop, _)) = flood_req.path_trace.last() else {
+            return Err(FloodError::EmptyPathTrace {
+                flood_id: flood_req.flood_id,
+                initiator_id: flood_req.initiator_id,
+            });
+        };
+
+        if !self.packet_send.contains_key(&previous_hop) {
+            return Err(FloodError::UnknownPreviousHop {
+                flood_id: flood_req.flood_id,
+                initiator_id: flood_req.initiator_id,
+                previous_hop,
+            });
+        }
+        Ok(previous_hop)
+    }
+
+    /// Sends back a FloodResponse with the path trace of `flood_req`,
+    /// which should already end with the drone
+    pub(super) fn respond_to_flood(
+        &mut self,
+        flood_req: FloodRequest,
+        session_id: u64,
+    ) -> Result<(), FloodError> {
+        let response = generate::flood_response(self.id, flood_req, session_id);
+
+        let next_hop = header::get_hop(&response.routing_header);
+        let Some((next_id, sender)) = next_hop
+            .and_then(|id| self.packet_send.get(&id).map(|sender| (id, sender.clone())))
+        else {
+            return Err(FloodError::Unroutable(Box::new(response)));
+        };
+
+        self.forward(response, next_id, sender);
+        Ok(())
+    }
+
+    /// Routes a [`FloodError`]:
+    /// - Requests which can't be answered are discarded and reported to the debug system
+    /// - Responses which can't be routed are sent to the simulation controller
+    pub(super) fn handle_flood_error(&self, error: FloodError) {
+        match error {
+            FloodError::EmptyPathTrace {
+                flood_id,
+                initiator_id,
+            } => self.debug(
+                DebugReason::MalformedFlood {
+                    flood_id,
+                    initiator_id,
+                },
+                None,
+            ),
+            FloodError::UnknownPreviousHop {
+                flood_id,
+                initiator_id,
+                previous_hop,
+            } => self.debug(
+                DebugReason::UnknownPreviousHop {
+                    flood_id,
+                    initiator_id,
+                    previous_hop,
+                },
+                None,
+            ),
+            FloodError::Unroutable(response) => {
+                self.debug(DebugReason::UnroutableFloodResponse, None);
+                self.send_controller(DroneEvent::ControllerShortcut(*response));
             }
         }
     }
 }
+
+impl FungiDrone {
+    /// Changes how the drone bounds the memory of the flood ids it has seen.
+    /// The flood ids seen so far are forgotten.
+    /// Bounds which would turn off duplicate detection, `Lru(0)` or a zero TTL, are raised to the smallest valid one.
+    pub fn set_flood_memory_policy(&mut self, policy: FloodMemoryPolicy) {
+        self.seen_flood_ids = FloodMemory::new(policy.clamped());
+    }
+
+    /// Number of entries in the drone's memory of seen flood ids
+    pub fn flood_memory_len(&self) -> usize {
+        self.seen_flood_ids.len()
+    }
+}
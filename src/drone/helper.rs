@@ -1,11 +1,21 @@
 /// functions to interact with SourceRoutingHeader
 pub(super) mod header {
     use wg_2024::network::{NodeId, SourceRoutingHeader};
+    use wg_2024::packet::{Packet, PacketType};
 
     pub fn get_hop(h: &SourceRoutingHeader) -> Option<NodeId> {
         h.hops.get(h.hop_index).cloned()
     }
 
+    /// The neighbor which sent a packet to the drone, if it can be told
+    pub fn previous_hop(packet: &Packet) -> Option<NodeId> {
+        if let PacketType::FloodRequest(flood_req) = &packet.pack_type {
+            return flood_req.path_trace.last().map(|(id, _)| *id);
+        }
+        let hop_index = packet.routing_header.hop_index.checked_sub(1)?;
+        packet.routing_header.hops.get(hop_index).copied()
+    }
+
     pub fn increment_index(h: &mut SourceRoutingHeader) {
         h.hop_index += 1;
     }
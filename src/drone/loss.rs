@@ -0,0 +1,88 @@
+use std::fmt::Debug;
+
+use rand::{Rng, RngCore};
+
+/// Decides which MsgFragments are dropped by the drone.
+pub trait LossModel: Debug + Send {
+    /// Rolls for the next fragment, returns `true` if it should be dropped.
+    /// `rng` is the drone's own generator, so seeded drones stay reproducible.
+    fn dropped(&mut self, rng: &mut dyn RngCore) -> bool;
+}
+
+/// Independent drops: every fragment is dropped with the same probability.
+/// This is the protocol's behaviour and the one set by `SetPacketDropRate`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bernoulli {
+    pub pdr: f32,
+}
+
+impl Bernoulli {
+    pub fn new(pdr: f32) -> Self {
+        Self { pdr }
+    }
+}
+
+impl LossModel for Bernoulli {
+    fn dropped(&mut self, rng: &mut dyn RngCore) -> bool {
+        let random_value = rng.gen_range(0.0..1.0);
+        random_value < self.pdr as f64
+    }
+}
+
+/// Bursty drops: a two state Markov chain where the link is either in a good or a bad state,
+/// each with its own drop probability.
+///
+/// After every fragment the link moves from good to bad with probability `p_good_to_bad`
+/// and from bad to good with probability `p_bad_to_good`,
+/// so the mean length of a burst is `1 / p_bad_to_good` fragments.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GilbertElliott {
+    pub p_good_to_bad: f32,
+    pub p_bad_to_good: f32,
+    pub loss_good: f32,
+    pub loss_bad: f32,
+    bad: bool,
+}
+
+impl GilbertElliott {
+    /// Creates the model starting in the good state
+    pub fn new(p_good_to_bad: f32, p_bad_to_good: f32, loss_good: f32, loss_bad: f32) -> Self {
+        Self {
+            p_good_to_bad,
+            p_bad_to_good,
+            loss_good,
+            loss_bad,
+            bad: false,
+        }
+    }
+
+    /// The classic Gilbert model: nothing is lost in the good state, everything in the bad one
+    pub fn gilbert(p_good_to_bad: f32, p_bad_to_good: f32) -> Self {
+        Self::new(p_good_to_bad, p_bad_to_good, 0.0, 1.0)
+    }
+
+    /// Whether the link is currently in the bad state
+    pub fn is_bad(&self) -> bool {
+        self.bad
+    }
+}
+
+impl LossModel for GilbertElliott {
+    fn dropped(&mut self, rng: &mut dyn RngCore) -> bool {
+        let loss = match self.bad {
+            true => self.loss_bad,
+            false => self.loss_good,
+        };
+        let dropped = rng.gen_range(0.0..1.0) < loss as f64;
+
+        let switch = match self.bad {
+            true => self.p_bad_to_good,
+            false => self.p_good_to_bad,
+        };
+        if rng.gen_range(0.0..1.0) < switch as f64 {
+            self.bad = !self.bad;
+        }
+
+        dropped
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use wg_2024::network::NodeId;
+use wg_2024::packet::{NackType, Packet, PacketType};
+
+use super::{header, FungiDrone};
+
+/// The type of a packet, without its content
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PacketKind {
+    MsgFragment,
+    Ack,
+    Nack,
+    FloodRequest,
+    FloodResponse,
+}
+
+impl PacketKind {
+    pub fn of(packet: &Packet) -> Self {
+        match packet.pack_type {
+            PacketType::MsgFragment(_) => PacketKind::MsgFragment,
+            PacketType::Ack(_) => PacketKind::Ack,
+            PacketType::Nack(_) => PacketKind::Nack,
+            PacketType::FloodRequest(_) => PacketKind::FloodRequest,
+            PacketType::FloodResponse(_) => PacketKind::FloodResponse,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PacketKind::MsgFragment => "msg_fragment",
+            PacketKind::Ack => "ack",
+            PacketKind::Nack => "nack",
+            PacketKind::FloodRequest => "flood_request",
+            PacketKind::FloodResponse => "flood_response",
+        }
+    }
+}
+
+/// The type of a Nack, without the node it refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum NackKind {
+    ErrorInRouting,
+    DestinationIsDrone,
+    Dropped,
+    UnexpectedRecipient,
+}
+
+impl NackKind {
+    pub fn of(nack_type: &NackType) -> Self {
+        match nack_type {
+            NackType::ErrorInRouting(_) => NackKind::ErrorInRouting,
+            NackType::DestinationIsDrone => NackKind::DestinationIsDrone,
+            NackType::Dropped => NackKind::Dropped,
+            NackType::UnexpectedRecipient(_) => NackKind::UnexpectedRecipient,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NackKind::ErrorInRouting => "error_in_routing",
+            NackKind::DestinationIsDrone => "destination_is_drone",
+            NackKind::Dropped => "dropped",
+            NackKind::UnexpectedRecipient => "unexpected_recipient",
+        }
+    }
+}
+
+/// The counters of a drone at one point in time
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    pub drone: NodeId,
+    /// Packets received, by the neighbor which sent them and by type.
+    /// Packets whose sender can't be told from their header are counted under `None`
+    pub received: BTreeMap<(Option<NodeId>, PacketKind), u64>,
+    /// Packets handed to the link towards a neighbor, by neighbor and by type,
+    /// including the ones which are later given up
+    pub forwarded: BTreeMap<(NodeId, PacketKind), u64>,
+    /// Nacks generated by the drone, by kind
+    pub nacks: BTreeMap<NackKind, u64>,
+    /// Packets dropped, the ones swallowed by a [`Byzantine`](super::Byzantine) mode included
+    pub dropped: u64,
+    /// Packets sent to the simulation controller as a shortcut
+    pub shortcuts: u64,
+}
+
+impl MetricsSnapshot {
+    /// Packets of type `kind` received from any neighbor
+    pub fn received_of(&self, kind: PacketKind) -> u64 {
+        self.received
+            .iter()
+            .filter(|((_, k), _)| *k == kind)
+            .map(|(_, n)| n)
+            .sum()
+    }
+
+    /// Packets of type `kind` forwarded to any neighbor
+    pub fn forwarded_of(&self, kind: PacketKind) -> u64 {
+        self.forwarded
+            .iter()
+            .filter(|((_, k), _)| *k == kind)
+            .map(|(_, n)| n)
+            .sum()
+    }
+
+    /// Packets of any type received from `neighbor`
+    pub fn received_from(&self, neighbor: NodeId) -> u64 {
+        self.received
+            .iter()
+            .filter(|((id, _), _)| *id == Some(neighbor))
+            .map(|(_, n)| n)
+            .sum()
+    }
+
+    /// Packets of any type forwarded to `neighbor`
+    pub fn forwarded_to(&self, neighbor: NodeId) -> u64 {
+        self.forwarded
+            .iter()
+            .filter(|((id, _), _)| *id == neighbor)
+            .map(|(_, n)| n)
+            .sum()
+    }
+}
+
+/// Handle to the counters of a drone.
+/// It can be cloned and sent to other threads, which read the counters while the drone is running
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    counters: Arc<Mutex<MetricsSnapshot>>,
+}
+
+impl Metrics {
+    pub(super) fn new(drone: NodeId) -> Self {
+        Metrics {
+            counters: Arc::new(Mutex::new(MetricsSnapshot {
+                drone,
+                ..MetricsSnapshot::default()
+            })),
+        }
+    }
+
+    /// A copy of the counters as they are now
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MetricsSnapshot> {
+        // The counters stay consistent even if a reader panicked while holding the lock
+        self.counters
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub(super) fn count_received(&self, packet: &Packet) {
+        let key = (header::previous_hop(packet), PacketKind::of(packet));
+        *self.lock().received.entry(key).or_default() += 1;
+    }
+
+    pub(super) fn count_forwarded(&self, packet: &Packet, next_id: NodeId) {
+        let key = (next_id, PacketKind::of(packet));
+        *self.lock().forwarded.entry(key).or_default() += 1;
+    }
+
+    /// Counts `packet` if it is a Nack
+    pub(super) fn count_nack(&self, packet: &Packet) {
+        if let PacketType::Nack(nack) = &packet.pack_type {
+            *self.lock().nacks.entry(NackKind::of(&nack.nack_type)).or_default() += 1;
+        }
+    }
+
+    pub(super) fn count_dropped(&self) {
+        self.lock().dropped += 1;
+    }
+
+    pub(super) fn count_shortcut(&self) {
+        self.lock().shortcuts += 1;
+    }
+}
+
+impl FungiDrone {
+    /// Handle to the drone's counters, to be read from any thread.
+    /// The counters are updated before any event is sent to the simulation controller,
+    /// so they are exact even when events are lost
+    pub fn metrics(&self) -> Metrics {
+        self.metrics.clone()
+    }
+}
@@ -1,9 +1,33 @@
+pub(super) mod byzantine;
+pub mod capture;
 pub(super) mod check_packet;
 pub(super) mod crashed;
 pub(super) mod debug;
+pub(super) mod faults;
 pub mod drone;
+pub(super) mod flood_memory;
 pub(super) mod flooding;
 pub(super) mod helper;
+pub(super) mod loss;
+pub(super) mod metrics;
+pub(super) mod outbound;
+pub(super) mod prometheus;
+pub(super) mod rate_limit;
+pub(super) mod replay;
 
 use helper::*;
+pub use byzantine::{Byzantine, TrafficFilter};
+pub use capture::{CaptureHeader, CaptureRecord, CapturedCommand, Direction, Trace};
+pub use check_packet::{check, CheckError, CheckOutcome, CheckPipeline, PacketCheck};
+pub use debug::{DebugCategory, DebugReason, DebugSink, Diagnostic};
+pub use crashed::{CrashTrigger, CrashedFloodPolicy, DrainSummary, Recovery};
 pub use drone::*;
+pub use flood_memory::{FloodMemoryPolicy, FLOOD_ID_WINDOW, MIN_FLOOD_TTL};
+pub use flooding::FloodError;
+pub use faults::{Corruption, FaultEvent, Reordering};
+pub use loss::{Bernoulli, GilbertElliott, LossModel};
+pub use metrics::{Metrics, MetricsSnapshot, NackKind, PacketKind};
+pub use outbound::{Latency, Priority, PriorityTable, RetryPolicy};
+pub use prometheus::PrometheusExporter;
+pub use replay::{replay, ReplayReport, ReplayedPacket};
+pub use rate_limit::{FloodRateLimit, ThrottleAction};
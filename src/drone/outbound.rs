@@ -0,0 +1,410 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{Sender, TrySendError};
+use rand::{Rng, RngCore};
+use wg_2024::network::NodeId;
+use wg_2024::packet::{Packet, PacketType};
+
+/// Transmission delay of the link towards a neighbor.
+/// Packets given a delay too long to be represented, such as `Duration::MAX`, are held
+/// until the drone stops or the neighbor is removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Latency {
+    /// Every packet waits the same amount of time
+    Fixed(Duration),
+    /// Every packet waits a random amount of time between `min` and `max`.
+    /// Packets on the same link can overtake each other.
+    Uniform { min: Duration, max: Duration },
+}
+
+impl Latency {
+    /// Draws the delay of a single packet
+    pub(super) fn sample(&self, rng: &mut dyn RngCore) -> Duration {
+        match *self {
+            Latency::Fixed(delay) => delay,
+            Latency::Uniform { min, max } if min < max => rng.gen_range(min..=max),
+            Latency::Uniform { min, .. } => min,
+        }
+    }
+}
+
+/// Scheduling class of an outbound packet.
+/// When packets wait for a neighbor's channel, higher classes are sent first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+}
+
+/// The [`Priority`] of every packet type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PriorityTable {
+    pub ack: Priority,
+    pub nack: Priority,
+    pub flood_response: Priority,
+    pub msg_fragment: Priority,
+    pub flood_request: Priority,
+}
+
+impl Default for PriorityTable {
+    /// Control packets go ahead of fragments and flood requests
+    fn default() -> Self {
+        Self {
+            ack: Priority::High,
+            nack: Priority::High,
+            flood_response: Priority::High,
+            msg_fragment: Priority::Normal,
+            flood_request: Priority::Normal,
+        }
+    }
+}
+
+impl PriorityTable {
+    /// Same as the default table, but flood requests are sent after everything else
+    pub fn flooding_last() -> Self {
+        Self {
+            flood_request: Priority::Low,
+            ..Self::default()
+        }
+    }
+
+    /// Every packet has the same priority, the queues are plain FIFOs
+    pub fn fifo() -> Self {
+        Self {
+            ack: Priority::Normal,
+            nack: Priority::Normal,
+            flood_response: Priority::Normal,
+            msg_fragment: Priority::Normal,
+            flood_request: Priority::Normal,
+        }
+    }
+
+    pub fn of(&self, packet: &Packet) -> Priority {
+        match packet.pack_type {
+            PacketType::Ack(_) => self.ack,
+            PacketType::Nack(_) => self.nack,
+            PacketType::FloodResponse(_) => self.flood_response,
+            PacketType::MsgFragment(_) => self.msg_fragment,
+            PacketType::FloodRequest(_) => self.flood_request,
+        }
+    }
+}
+
+/// Decides how long packets wait when the next drone's channel is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Packets waiting per neighbor, packets beyond it are given up immediately
+    pub capacity: usize,
+    /// Time between two attempts.
+    /// An interval too long to be represented, such as `Duration::MAX`, never retries:
+    /// the packets wait until the drone stops
+    pub interval: Duration,
+    /// Retries after which a packet is given up, `None` to retry forever
+    pub max_retries: Option<u32>,
+    /// Time after which a waiting packet is given up, `None` to wait forever
+    pub max_age: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            capacity: 64,
+            interval: Duration::from_millis(5),
+            max_retries: Some(20),
+            max_age: Some(Duration::from_millis(500)),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retries: packets which don't fit in the next drone's channel are given up at once
+    pub fn no_retry() -> Self {
+        Self {
+            capacity: 0,
+            ..Self::default()
+        }
+    }
+
+    fn expired(&self, pending: &PendingPacket, now: Instant) -> bool {
+        let too_many = matches!(self.max_retries, Some(max) if pending.retries >= max);
+        let too_old = matches!(self.max_age, Some(max) if now.duration_since(pending.queued_at) >= max);
+        too_many || too_old
+    }
+}
+
+/// A packet waiting for the next drone's channel to have room
+#[derive(Debug)]
+struct PendingPacket {
+    packet: Packet,
+    priority: Priority,
+    sender: Sender<Packet>,
+    retries: u32,
+    queued_at: Instant,
+}
+
+/// Packets which left the outbound queues without reaching the next drone
+#[derive(Debug, Default)]
+pub(super) struct Undelivered {
+    /// Given up according to the [`RetryPolicy`]
+    pub(super) gave_up: Vec<Packet>,
+    /// The next drone's channel was closed, with the id of the next drone
+    pub(super) disconnected: Vec<(Packet, NodeId)>,
+}
+
+/// A packet waiting for its link's latency to pass
+#[derive(Debug)]
+pub(super) struct DelayedPacket {
+    /// `None` if the delay is too long to be represented, the packet only leaves when the queues are flushed
+    due: Option<Instant>,
+    seq: u64,
+    pub(super) packet: Packet,
+    pub(super) next_id: NodeId,
+    pub(super) sender: Sender<Packet>,
+}
+
+impl PartialEq for DelayedPacket {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for DelayedPacket {}
+
+impl PartialOrd for DelayedPacket {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for DelayedPacket {
+    /// Earliest due first and packets which are never due last,
+    /// ties are broken by the order in which packets were forwarded
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.due.is_none(), self.due, self.seq).cmp(&(other.due.is_none(), other.due, other.seq))
+    }
+}
+
+/// Packets forwarded by the drone which haven't been handed to the neighbor's channel yet
+#[derive(Debug, Default)]
+pub(super) struct Outbound {
+    latency: HashMap<NodeId, Latency>,
+    delayed: BinaryHeap<Reverse<DelayedPacket>>,
+    seq: u64,
+    retry: RetryPolicy,
+    priorities: PriorityTable,
+    pending: HashMap<NodeId, VecDeque<PendingPacket>>,
+    next_retry: Option<Instant>,
+}
+
+impl Outbound {
+    pub(super) fn latency(&self, next_id: NodeId) -> Option<Latency> {
+        self.latency.get(&next_id).copied()
+    }
+
+    pub(super) fn set_latency(&mut self, next_id: NodeId, latency: Latency) {
+        self.latency.insert(next_id, latency);
+    }
+
+    pub(super) fn clear_latency(&mut self, next_id: NodeId) {
+        self.latency.remove(&next_id);
+    }
+
+    pub(super) fn set_retry_policy(&mut self, retry: RetryPolicy) {
+        self.retry = retry;
+    }
+
+    /// Whether packets to `next_id` are waiting for room in its channel.
+    /// New packets must go through the queue to respect the link's order and priorities.
+    pub(super) fn is_backlogged(&self, next_id: NodeId) -> bool {
+        self.pending.contains_key(&next_id)
+    }
+
+    pub(super) fn set_priorities(&mut self, priorities: PriorityTable) {
+        self.priorities = priorities;
+    }
+
+    /// Queues a packet which didn't fit in the next drone's channel,
+    /// behind the waiting packets of the same or higher [`Priority`].
+    ///
+    /// If the neighbor's queue is full, the newest packet of the lowest priority is given up to make room,
+    /// unless it has a higher priority than the new one.
+    /// Returns the packet given up, `None` if nothing has been given up.
+    pub(super) fn queue(
+        &mut self,
+        packet: Packet,
+        next_id: NodeId,
+        sender: Sender<Packet>,
+    ) -> Option<Packet> {
+        let priority = self.priorities.of(&packet);
+        let queue = self.pending.entry(next_id).or_default();
+
+        let mut given_up = None;
+        if queue.len() >= self.retry.capacity {
+            match queue.back() {
+                Some(last) if last.priority < priority => {
+                    given_up = queue.pop_back().map(|last| last.packet);
+                }
+                _ => {
+                    if queue.is_empty() {
+                        self.pending.remove(&next_id);
+                    }
+                    return Some(packet);
+                }
+            }
+        }
+
+        let now = Instant::now();
+        let position = queue
+            .iter()
+            .position(|pending| pending.priority < priority)
+            .unwrap_or(queue.len());
+        queue.insert(
+            position,
+            PendingPacket {
+                packet,
+                priority,
+                sender,
+                retries: 0,
+                queued_at: now,
+            },
+        );
+        if self.next_retry.is_none() {
+            self.next_retry = now.checked_add(self.retry.interval);
+        }
+        given_up
+    }
+
+    /// The instant at which the queued packets should be tried again
+    pub(super) fn next_retry(&self) -> Option<Instant> {
+        self.next_retry
+    }
+
+    /// Tries again to send the queued packets, in order, if their retry is due at `now`.
+    /// A neighbor's queue stops at the first packet which still doesn't fit,
+    /// so lower priorities never overtake higher ones.
+    pub(super) fn retry(&mut self, now: Instant) -> Undelivered {
+        let mut undelivered = Undelivered::default();
+        if !matches!(self.next_retry, Some(retry) if retry <= now) {
+            return undelivered;
+        }
+
+        for (&next_id, queue) in self.pending.iter_mut() {
+            while let Some(mut pending) = queue.pop_front() {
+                if self.retry.expired(&pending, now) {
+                    undelivered.gave_up.push(pending.packet);
+                    continue;
+                }
+
+                match pending.sender.try_send(pending.packet) {
+                    Ok(()) => (),
+                    Err(TrySendError::Full(packet)) => {
+                        pending.packet = packet;
+                        pending.retries += 1;
+                        queue.push_front(pending);
+                        break;
+                    }
+                    Err(TrySendError::Disconnected(packet)) => {
+                        undelivered.disconnected.push((packet, next_id));
+                    }
+                }
+            }
+        }
+
+        self.pending.retain(|_, queue| !queue.is_empty());
+        self.next_retry = match self.pending.is_empty() {
+            true => None,
+            false => now.checked_add(self.retry.interval),
+        };
+        undelivered
+    }
+
+    /// Tries every queued packet one last time, the ones which still don't fit are given up
+    pub(super) fn drain_pending(&mut self) -> Undelivered {
+        let mut undelivered = Undelivered::default();
+
+        for (next_id, queue) in self.pending.drain() {
+            let mut full = false;
+            for pending in queue {
+                if full {
+                    undelivered.gave_up.push(pending.packet);
+                    continue;
+                }
+                match pending.sender.try_send(pending.packet) {
+                    Ok(()) => (),
+                    Err(TrySendError::Full(packet)) => {
+                        full = true;
+                        undelivered.gave_up.push(packet);
+                    }
+                    Err(TrySendError::Disconnected(packet)) => {
+                        undelivered.disconnected.push((packet, next_id));
+                    }
+                }
+            }
+        }
+
+        self.next_retry = None;
+        undelivered
+    }
+
+    /// Holds a packet until `delay` has passed
+    pub(super) fn delay(
+        &mut self,
+        packet: Packet,
+        next_id: NodeId,
+        sender: Sender<Packet>,
+        delay: Duration,
+    ) {
+        self.seq += 1;
+        self.delayed.push(Reverse(DelayedPacket {
+            due: Instant::now().checked_add(delay),
+            seq: self.seq,
+            packet,
+            next_id,
+            sender,
+        }));
+    }
+
+    /// The instant at which the next delayed packet is due
+    pub(super) fn next_due(&self) -> Option<Instant> {
+        self.delayed.peek().and_then(|Reverse(p)| p.due)
+    }
+
+    /// Takes the earliest delayed packet if it is due at `now`
+    pub(super) fn pop_due(&mut self, now: Instant) -> Option<DelayedPacket> {
+        if self.next_due()? > now {
+            return None;
+        }
+        self.delayed.pop().map(|Reverse(p)| p)
+    }
+
+    /// Takes the delayed and queued packets for a neighbor which has been removed,
+    /// in the order they would have been sent
+    pub(super) fn remove_neighbor(&mut self, next_id: NodeId) -> Vec<Packet> {
+        let (mut removed, delayed): (Vec<_>, Vec<_>) = std::mem::take(&mut self.delayed)
+            .into_iter()
+            .partition(|Reverse(p)| p.next_id == next_id);
+        self.delayed = delayed.into();
+        removed.sort_unstable_by(|Reverse(a), Reverse(b)| a.cmp(b));
+
+        let pending = self.pending.remove(&next_id).unwrap_or_default();
+        if self.pending.is_empty() {
+            self.next_retry = None;
+        }
+
+        removed
+            .into_iter()
+            .map(|Reverse(p)| p.packet)
+            .chain(pending.into_iter().map(|pending| pending.packet))
+            .collect()
+    }
+
+    /// Takes every delayed packet, due or not, in the order they would have been sent
+    pub(super) fn drain(&mut self) -> Vec<DelayedPacket> {
+        let mut packets = std::mem::take(&mut self.delayed).into_vec();
+        packets.sort_unstable_by(|Reverse(a), Reverse(b)| a.cmp(b));
+        packets.into_iter().map(|Reverse(p)| p).collect()
+    }
+}
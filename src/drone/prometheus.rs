@@ -0,0 +1,137 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::thread;
+
+use super::metrics::{Metrics, MetricsSnapshot};
+
+/// Exports the counters of a set of drones in the Prometheus text exposition format,
+/// labelled by drone id, neighbor and packet type
+#[derive(Debug, Clone, Default)]
+pub struct PrometheusExporter {
+    drones: Vec<Metrics>,
+}
+
+impl PrometheusExporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the counters of a drone, from [`FungiDrone::metrics`](super::FungiDrone::metrics)
+    pub fn add(&mut self, metrics: Metrics) {
+        self.drones.push(metrics);
+    }
+
+    /// The current counters of every drone, in the exposition format
+    pub fn render(&self) -> String {
+        let snapshots = self
+            .drones
+            .iter()
+            .map(Metrics::snapshot)
+            .collect::<Vec<MetricsSnapshot>>();
+        render(&snapshots)
+    }
+
+    /// Writes the current counters to `path`.
+    /// The file is replaced at once, so a scraper never reads half of it
+    pub fn write_file(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, self.render())?;
+        fs::rename(tmp, path)
+    }
+
+    /// Serves the counters over HTTP on `127.0.0.1:port`, from a thread of its own.
+    /// Every request gets the current counters, whatever its path.
+    ///
+    /// Returns the address the endpoint is bound to, port `0` picks a free one
+    pub fn serve(self, port: u16) -> io::Result<SocketAddr> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        let addr = listener.local_addr()?;
+
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                // A scraper which goes away mid-request doesn't stop the endpoint
+                let _ = self.respond(stream);
+            }
+        });
+        Ok(addr)
+    }
+
+    fn respond(&self, mut stream: TcpStream) -> io::Result<()> {
+        // The request is read up to the end of its headers and ignored
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 0 && line != "\r\n" {
+            line.clear();
+        }
+
+        let body = self.render();
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    }
+}
+
+/// Renders the counters of some drones in the Prometheus text exposition format
+fn render(snapshots: &[MetricsSnapshot]) -> String {
+    let mut out = String::new();
+
+    family(&mut out, "fungi_drone_packets_received_total", "Packets received by the drone");
+    for s in snapshots {
+        for ((neighbor, kind), n) in &s.received {
+            let neighbor = neighbor.map_or("unknown".to_string(), |id| id.to_string());
+            let _ = writeln!(
+                out,
+                "fungi_drone_packets_received_total{{drone=\"{}\",neighbor=\"{neighbor}\",packet_type=\"{}\"}} {n}",
+                s.drone,
+                kind.name()
+            );
+        }
+    }
+
+    family(&mut out, "fungi_drone_packets_forwarded_total", "Packets handed to the link towards a neighbor");
+    for s in snapshots {
+        for ((neighbor, kind), n) in &s.forwarded {
+            let _ = writeln!(
+                out,
+                "fungi_drone_packets_forwarded_total{{drone=\"{}\",neighbor=\"{neighbor}\",packet_type=\"{}\"}} {n}",
+                s.drone,
+                kind.name()
+            );
+        }
+    }
+
+    family(&mut out, "fungi_drone_nacks_total", "Nacks generated by the drone");
+    for s in snapshots {
+        for (kind, n) in &s.nacks {
+            let _ = writeln!(
+                out,
+                "fungi_drone_nacks_total{{drone=\"{}\",nack_type=\"{}\"}} {n}",
+                s.drone,
+                kind.name()
+            );
+        }
+    }
+
+    family(&mut out, "fungi_drone_packets_dropped_total", "Packets reported as dropped");
+    for s in snapshots {
+        let _ = writeln!(out, "fungi_drone_packets_dropped_total{{drone=\"{}\"}} {}", s.drone, s.dropped);
+    }
+
+    family(&mut out, "fungi_drone_shortcuts_total", "Packets sent to the simulation controller as a shortcut");
+    for s in snapshots {
+        let _ = writeln!(out, "fungi_drone_shortcuts_total{{drone=\"{}\"}} {}", s.drone, s.shortcuts);
+    }
+
+    out
+}
+
+fn family(out: &mut String, name: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} counter");
+}
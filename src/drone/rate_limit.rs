@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use wg_2024::controller::DroneEvent;
+use wg_2024::network::{NodeId, SourceRoutingHeader};
+use wg_2024::packet::{FloodRequest, NodeType, Packet, PacketType};
+
+use super::debug::DebugReason;
+use super::faults::FaultEvent;
+use super::FungiDrone;
+
+/// Limit on the new floods each initiator can start through the drone, as a token bucket
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FloodRateLimit {
+    /// Floods an initiator can start at once
+    pub burst: u32,
+    /// Floods per second an initiator gets back
+    pub per_second: f32,
+    /// What happens to the floods over the limit
+    pub action: ThrottleAction,
+}
+
+/// What the drone does with a flood over the limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThrottleAction {
+    /// The request is dropped, and sent to the simulation controller as `PacketDropped`,
+    /// even if the logging of flood requests is disabled
+    Drop,
+    /// The request is answered with a FloodResponse ending with the drone, as if it had no other neighbor.
+    /// Nothing is dropped: the request is sent to the simulation controller through the debug shortcut, if it is set
+    Respond,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f32,
+    refilled_at: Instant,
+}
+
+/// The token buckets of every initiator
+#[derive(Debug, Default)]
+pub(super) struct FloodLimiter {
+    limit: Option<FloodRateLimit>,
+    buckets: HashMap<NodeId, Bucket>,
+}
+
+impl FloodLimiter {
+    /// Takes a token from the initiator's bucket.
+    /// Returns `false` if the bucket is empty and the flood is over the limit
+    fn allow(&mut self, initiator_id: NodeId) -> bool {
+        let Some(limit) = self.limit else {
+            return true;
+        };
+
+        let now = Instant::now();
+        let bucket = self.buckets.entry(initiator_id).or_insert(Bucket {
+            tokens: limit.burst as f32,
+            refilled_at: now,
+        });
+
+        let refill = now.duration_since(bucket.refilled_at).as_secs_f32() * limit.per_second;
+        bucket.tokens = (bucket.tokens + refill).min(limit.burst as f32);
+        bucket.refilled_at = now;
+
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+}
+
+impl FungiDrone {
+    /// Checks a new flood against the initiator's limit, throttling it if it is over.
+    ///
+    /// Returns the request back if it can be flooded, `None` if it has been throttled
+    pub(super) fn limit_flood(
+        &mut self,
+        mut flood_req: FloodRequest,
+        session_id: u64,
+    ) -> Option<FloodRequest> {
+        if self.flood_limiter.allow(flood_req.initiator_id) {
+            return Some(flood_req);
+        }
+        let action = self.flood_limiter.limit?.action;
+
+        let request = Packet {
+            pack_type: PacketType::FloodRequest(flood_req.clone()),
+            routing_header: SourceRoutingHeader {
+                hop_index: 0,
+                hops: Vec::new(),
+            },
+            session_id,
+        };
+        self.debug(
+            DebugReason::FloodThrottled {
+                flood_id: flood_req.flood_id,
+                initiator_id: flood_req.initiator_id,
+            },
+            Some(request.clone()),
+        );
+        self.report_fault(FaultEvent::Throttled {
+            drone: self.id,
+            initiator_id: flood_req.initiator_id,
+            flood_id: flood_req.flood_id,
+            action,
+        });
+        match action {
+            // Throttling is a decision of the drone, so it is reported even if the request log is disabled
+            ThrottleAction::Drop => {
+                self.metrics.count_dropped();
+                self.send_controller(DroneEvent::PacketDropped(request));
+            }
+            // The request is answered rather than lost, so it isn't reported as dropped
+            ThrottleAction::Respond => {
+                flood_req.path_trace.push((self.id, NodeType::Drone));
+                if let Err(error) = self.respond_to_flood(flood_req, session_id) {
+                    self.handle_flood_error(error);
+                }
+            }
+        }
+        None
+    }
+
+    /// Limits the new floods each initiator can start through the drone, `None` to remove the limit
+    pub fn set_flood_rate_limit(&mut self, limit: Option<FloodRateLimit>) {
+        self.flood_limiter = FloodLimiter {
+            limit,
+            buckets: HashMap::new(),
+        };
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use wg_2024::controller::DroneCommand;
+use wg_2024::drone::Drone;
+use wg_2024::network::NodeId;
+use wg_2024::packet::Packet;
+
+use super::capture::{CaptureRecord, CapturedCommand, Direction, Trace};
+use super::FungiDrone;
+
+/// A packet forwarded by the drone, as compared by the replay
+#[derive(Debug, Clone)]
+pub struct ReplayedPacket {
+    pub neighbor: Option<NodeId>,
+    pub packet: Packet,
+}
+
+/// The outcome of a replay
+#[derive(Debug, Clone)]
+pub struct ReplayReport {
+    /// Packets forwarded in the capture, in order
+    pub expected: Vec<ReplayedPacket>,
+    /// Packets forwarded by the replayed drone, in order
+    pub actual: Vec<ReplayedPacket>,
+    /// Index of the first packet which differs, `None` if the outputs are the same
+    pub first_mismatch: Option<usize>,
+}
+
+impl ReplayReport {
+    pub fn matches(&self) -> bool {
+        self.first_mismatch.is_none()
+    }
+}
+
+/// Feeds the inputs of a capture into a fresh drone and compares what it forwards with the capture.
+///
+/// The drone is built with the id, neighbors, drop rate and seed in the capture header,
+/// then `setup` is called to give it the configuration the captured drone had, such as its loss model.
+/// Packets and commands are handled one after the other on the calling thread, in the captured order,
+/// as a crashed drone while the capture says the drone was crashed. Recoveries are captured,
+/// so drones which crash and recover more than once replay the same way.
+///
+/// Packets are compared through their serialized form, the timestamps are ignored.
+/// Features which depend on wall-clock time, such as latency, reordering or delayed crash triggers,
+/// may not replay the same way.
+pub fn replay(trace: &Trace, setup: impl FnOnce(&mut FungiDrone)) -> io::Result<ReplayReport> {
+    let header = &trace.header;
+    let (event_send, event_recv) = unbounded();
+    // The drone is driven directly, its own channels stay empty
+    let (_command_send, command_recv) = unbounded();
+    let (_packet_send, packet_recv) = unbounded();
+    let mut links = Links::default();
+
+    let mut drone = FungiDrone::new(
+        header.drone,
+        event_send,
+        command_recv,
+        packet_recv,
+        header
+            .neighbors
+            .iter()
+            .map(|&id| (id, links.open(id)))
+            .collect(),
+        header.pdr,
+    );
+    drone.set_seed(header.seed);
+    setup(&mut drone);
+
+    let output = SharedBuffer::default();
+    drone.set_capture(output.clone())?;
+
+    let mut crashed = false;
+    for record in &trace.records {
+        match record {
+            CaptureRecord::Packet {
+                direction: Direction::In,
+                packet,
+                ..
+            } => {
+                if crashed {
+                    drone.handle_packet_crashed(packet.clone());
+                    continue;
+                }
+                drone.handle_packet_internal(packet.clone());
+                drone.tick_crash_schedule(true);
+                crashed = drone.scheduled_crash_due();
+            }
+            CaptureRecord::Packet { .. } => continue,
+            CaptureRecord::Command {
+                command: CapturedCommand::Crash,
+                ..
+            } => {
+                // A crashed drone doesn't capture the crash commands it ignores
+                crashed = true;
+            }
+            CaptureRecord::Command { command, .. } => {
+                if crashed {
+                    drone.handle_command_crashed(links.command(*command));
+                    continue;
+                }
+                drone.handle_command_internal(Ok(links.command(*command)));
+                drone.tick_crash_schedule(false);
+                crashed = drone.scheduled_crash_due();
+            }
+            CaptureRecord::Recovered { .. } => {
+                drone.recover();
+                crashed = false;
+                continue;
+            }
+            CaptureRecord::Reseeded { seed, .. } => {
+                drone.set_seed(*seed);
+                continue;
+            }
+        }
+
+        if crashed {
+            drone.enter_crash();
+        }
+    }
+
+    drone.flush_reorder_window();
+    drone.flush_outbound();
+    drone.stop_capture();
+    drop(event_recv);
+
+    let replayed = Trace::read(output.contents().as_slice())?;
+    let expected = forwarded(trace);
+    let actual = forwarded(&replayed);
+    let first_mismatch = (0..expected.len().max(actual.len())).find(|&i| {
+        match (expected.get(i), actual.get(i)) {
+            (Some(e), Some(a)) => !same_packet(e, a),
+            _ => true,
+        }
+    });
+
+    Ok(ReplayReport {
+        expected,
+        actual,
+        first_mismatch,
+    })
+}
+
+/// The packets forwarded in a capture
+fn forwarded(trace: &Trace) -> Vec<ReplayedPacket> {
+    trace
+        .records
+        .iter()
+        .filter_map(|record| match record {
+            CaptureRecord::Packet {
+                direction: Direction::Out,
+                neighbor,
+                packet,
+                ..
+            } => Some(ReplayedPacket {
+                neighbor: *neighbor,
+                packet: packet.clone(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Packets aren't comparable, their serialized forms are
+fn same_packet(expected: &ReplayedPacket, actual: &ReplayedPacket) -> bool {
+    expected.neighbor == actual.neighbor
+        && serde_json::to_value(&expected.packet).ok() == serde_json::to_value(&actual.packet).ok()
+}
+
+/// The neighbors' ends of the replayed drone's links.
+/// They are kept open so that the drone never sees a neighbor disconnect
+#[derive(Default)]
+struct Links {
+    receivers: HashMap<NodeId, Vec<Receiver<Packet>>>,
+}
+
+impl Links {
+    fn open(&mut self, id: NodeId) -> Sender<Packet> {
+        let (send, recv) = unbounded();
+        self.receivers.entry(id).or_default().push(recv);
+        send
+    }
+
+    fn command(&mut self, command: CapturedCommand) -> DroneCommand {
+        match command {
+            CapturedCommand::AddSender(id) => DroneCommand::AddSender(id, self.open(id)),
+            CapturedCommand::RemoveSender(id) => DroneCommand::RemoveSender(id),
+            CapturedCommand::SetPacketDropRate(pdr) => DroneCommand::SetPacketDropRate(pdr),
+            CapturedCommand::Crash => DroneCommand::Crash,
+        }
+    }
+}
+
+/// An in-memory capture which can be read back once the drone is done with it
+#[derive(Debug, Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    fn contents(&self) -> Vec<u8> {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
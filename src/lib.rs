@@ -1,4 +1,4 @@
 pub mod drone;
+pub mod sim;
 
 pub use drone::*;
-
@@ -0,0 +1,239 @@
+//! Builds a network of fungi drones from a wg_2024 TOML `Config`.
+//!
+//! Every node gets its own packet channel. Each drone is spawned on a thread of its own,
+//! with its `pdr` and the senders of its neighbors, while clients and servers are left to the caller:
+//! their channel ends are returned next to the controller side of every drone.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::thread::{self, JoinHandle};
+
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use wg_2024::config::Config;
+use wg_2024::controller::{DroneCommand, DroneEvent};
+use wg_2024::drone::Drone;
+use wg_2024::network::NodeId;
+use wg_2024::packet::Packet;
+
+use crate::drone::FungiDrone;
+
+/// Why a network couldn't be built
+#[derive(Debug)]
+pub enum SimError {
+    /// The config file couldn't be read
+    Io(io::Error),
+    /// The config isn't a valid wg_2024 `Config`
+    Parse(toml::de::Error),
+    /// Two nodes have the same id
+    DuplicateId(NodeId),
+    /// A node is connected to an id which isn't in the config
+    UnknownNeighbor { node: NodeId, neighbor: NodeId },
+}
+
+impl fmt::Display for SimError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimError::Io(error) => write!(f, "can't read the config: {error}"),
+            SimError::Parse(error) => write!(f, "invalid config: {error}"),
+            SimError::DuplicateId(id) => write!(f, "node {id} appears more than once"),
+            SimError::UnknownNeighbor { node, neighbor } => {
+                write!(f, "node {node} is connected to {neighbor}, which isn't in the config")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SimError {}
+
+impl From<io::Error> for SimError {
+    fn from(error: io::Error) -> Self {
+        SimError::Io(error)
+    }
+}
+
+impl From<toml::de::Error> for SimError {
+    fn from(error: toml::de::Error) -> Self {
+        SimError::Parse(error)
+    }
+}
+
+/// Parses a wg_2024 network config
+pub fn parse_config(text: &str) -> Result<Config, SimError> {
+    Ok(toml::from_str(text)?)
+}
+
+/// Reads and parses the wg_2024 network config at `path`
+pub fn load_config(path: impl AsRef<Path>) -> Result<Config, SimError> {
+    parse_config(&fs::read_to_string(path)?)
+}
+
+/// The simulation controller's side of a running drone
+#[derive(Debug)]
+pub struct DroneHandle {
+    pub command_send: Sender<DroneCommand>,
+    pub event_recv: Receiver<DroneEvent>,
+    /// Sender of the drone's own packet channel
+    pub packet_send: Sender<Packet>,
+    pub thread: JoinHandle<()>,
+}
+
+/// The channel ends of a client or server, which are left to the caller to run
+#[derive(Debug)]
+pub struct NodeHandle {
+    /// The node's own packet channel
+    pub packet_recv: Receiver<Packet>,
+    /// Senders of the drones the node is connected to
+    pub packet_send: HashMap<NodeId, Sender<Packet>>,
+}
+
+/// A running network of fungi drones
+#[derive(Debug)]
+pub struct Network {
+    pub drones: HashMap<NodeId, DroneHandle>,
+    pub clients: HashMap<NodeId, NodeHandle>,
+    pub servers: HashMap<NodeId, NodeHandle>,
+    /// Drone neighbors of every drone, as in the config
+    links: HashMap<NodeId, Vec<NodeId>>,
+}
+
+impl Network {
+    /// Creates every channel of `config` and spawns its drones
+    pub fn spawn(config: &Config) -> Result<Network, SimError> {
+        Network::spawn_with(config, |_| ())
+    }
+
+    /// Creates every channel of `config` and spawns its drones,
+    /// calling `setup` on each drone before it runs, for example to seed it
+    pub fn spawn_with(
+        config: &Config,
+        mut setup: impl FnMut(&mut FungiDrone),
+    ) -> Result<Network, SimError> {
+        let connections = config
+            .drone
+            .iter()
+            .map(|d| (d.id, &d.connected_node_ids))
+            .chain(config.client.iter().map(|c| (c.id, &c.connected_drone_ids)))
+            .chain(config.server.iter().map(|s| (s.id, &s.connected_drone_ids)))
+            .collect::<Vec<(NodeId, &Vec<NodeId>)>>();
+
+        let mut channels = HashMap::new();
+        for &(id, _) in &connections {
+            if channels.insert(id, unbounded::<Packet>()).is_some() {
+                return Err(SimError::DuplicateId(id));
+            }
+        }
+
+        let mut senders = HashMap::new();
+        for &(node, neighbors) in &connections {
+            let mut packet_send = HashMap::new();
+            for &neighbor in neighbors {
+                let Some((send, _)) = channels.get(&neighbor) else {
+                    return Err(SimError::UnknownNeighbor { node, neighbor });
+                };
+                packet_send.insert(neighbor, send.clone());
+            }
+            senders.insert(node, packet_send);
+        }
+
+        let drone_ids = config.drone.iter().map(|d| d.id).collect::<HashSet<NodeId>>();
+        let mut network = Network {
+            drones: HashMap::new(),
+            clients: HashMap::new(),
+            servers: HashMap::new(),
+            links: HashMap::new(),
+        };
+
+        for drone_config in &config.drone {
+            let id = drone_config.id;
+            let (packet_send, packet_recv) = channels.remove(&id).unwrap();
+            let (command_send, command_recv) = unbounded();
+            let (event_send, event_recv) = unbounded();
+
+            let mut drone = FungiDrone::new(
+                id,
+                event_send,
+                command_recv,
+                packet_recv,
+                senders.remove(&id).unwrap(),
+                drone_config.pdr,
+            );
+            setup(&mut drone);
+
+            let thread = thread::Builder::new()
+                .name(format!("drone-{id}"))
+                .spawn(move || drone.run())?;
+
+            network.links.insert(
+                id,
+                drone_config
+                    .connected_node_ids
+                    .iter()
+                    .copied()
+                    .filter(|neighbor| drone_ids.contains(neighbor))
+                    .collect(),
+            );
+            network.drones.insert(
+                id,
+                DroneHandle {
+                    command_send,
+                    event_recv,
+                    packet_send,
+                    thread,
+                },
+            );
+        }
+
+        for client in &config.client {
+            let (_, packet_recv) = channels.remove(&client.id).unwrap();
+            let packet_send = senders.remove(&client.id).unwrap();
+            network.clients.insert(client.id, NodeHandle { packet_recv, packet_send });
+        }
+        for server in &config.server {
+            let (_, packet_recv) = channels.remove(&server.id).unwrap();
+            let packet_send = senders.remove(&server.id).unwrap();
+            network.servers.insert(server.id, NodeHandle { packet_recv, packet_send });
+        }
+
+        Ok(network)
+    }
+
+    /// Crashes every drone and waits for their threads to end.
+    /// Each crashed drone is removed from its neighbors, so that their drain can end.
+    ///
+    /// Returns the ids of the drones whose thread panicked, if any
+    pub fn shutdown(self) -> Result<(), Vec<NodeId>> {
+        for drone in self.drones.values() {
+            let _ = drone.command_send.send(DroneCommand::Crash);
+        }
+        for (&id, neighbors) in &self.links {
+            for neighbor in neighbors {
+                let _ = self.drones[neighbor]
+                    .command_send
+                    .send(DroneCommand::RemoveSender(id));
+            }
+        }
+
+        // The clients and servers hold senders of the drones too
+        drop(self.clients);
+        drop(self.servers);
+
+        let mut panicked = self
+            .drones
+            .into_iter()
+            .filter_map(|(id, drone)| {
+                drop(drone.packet_send);
+                drop(drone.command_send);
+                drone.thread.join().err().map(|_| id)
+            })
+            .collect::<Vec<NodeId>>();
+
+        if panicked.is_empty() {
+            return Ok(());
+        }
+        panicked.sort_unstable();
+        Err(panicked)
+    }
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use std::fs;
+
+use common::{Harness, TIMEOUT};
+use fungi_drone::capture::{CAPTURE_FORMAT, CAPTURE_VERSION};
+use fungi_drone::{CaptureHeader, CaptureRecord, CapturedCommand, Direction};
+use wg_2024::network::SourceRoutingHeader;
+use wg_2024::packet::{Fragment, Packet, PacketType};
+
+#[test]
+fn records_packets_and_commands() {
+    let path = std::env::temp_dir().join(format!("fungi-capture-{}.jsonl", std::process::id()));
+    let harness = Harness::spawn(2, &[1, 3], |drone| {
+        drone.set_seed(7);
+        drone.capture_to_file(&path).unwrap();
+    });
+
+    let packet = Packet {
+        routing_header: SourceRoutingHeader {
+            hop_index: 1,
+            hops: vec![1, 2, 3],
+        },
+        session_id: 4,
+        pack_type: PacketType::MsgFragment(Fragment {
+            fragment_index: 5,
+            total_n_fragments: 6,
+            length: 3,
+            data: [1; 128],
+        }),
+    };
+    harness.packets.send(packet).unwrap();
+    harness.neighbors[&3].recv_timeout(TIMEOUT).unwrap();
+    harness.stop();
+
+    let capture = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    let mut lines = capture.lines();
+
+    let header: CaptureHeader = serde_json::from_str(lines.next().unwrap()).unwrap();
+    assert_eq!(header.format, CAPTURE_FORMAT);
+    assert_eq!(header.version, CAPTURE_VERSION);
+    assert_eq!((header.drone, header.seed), (2, 7));
+
+    let records = lines
+        .map(|line| serde_json::from_str::<CaptureRecord>(line).unwrap())
+        .collect::<Vec<CaptureRecord>>();
+    assert_eq!(records.len(), 3);
+    assert!(matches!(
+        records[2],
+        CaptureRecord::Command {
+            command: CapturedCommand::Crash,
+            ..
+        }
+    ));
+
+    let CaptureRecord::Packet {
+        direction, neighbor, ..
+    } = &records[0]
+    else {
+        panic!("expected a packet record");
+    };
+    assert_eq!((*direction, *neighbor), (Direction::In, Some(1)));
+
+    let CaptureRecord::Packet {
+        direction,
+        neighbor,
+        packet,
+        ..
+    } = &records[1]
+    else {
+        panic!("expected a packet record");
+    };
+    assert_eq!((*direction, *neighbor), (Direction::Out, Some(3)));
+    assert_eq!(packet.routing_header.hop_index, 2);
+    let PacketType::MsgFragment(fragment) = &packet.pack_type else {
+        panic!("expected a fragment, got {packet:?}");
+    };
+    assert_eq!(fragment.fragment_index, 5);
+    assert_eq!((fragment.length, fragment.data), (3, [1; 128]));
+}
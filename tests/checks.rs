@@ -0,0 +1,126 @@
+mod common;
+
+use common::{fragment, Harness, TIMEOUT};
+use fungi_drone::{check, CheckError, CheckOutcome, CheckPipeline, FungiDrone, PacketCheck};
+use wg_2024::controller::DroneEvent;
+use wg_2024::network::SourceRoutingHeader;
+use wg_2024::packet::{Packet, PacketType};
+
+/// Shortcuts every packet of another session
+#[derive(Debug)]
+struct OnlySession(u64);
+
+impl PacketCheck for OnlySession {
+    fn check(&self, packet: Packet, _drone: &mut FungiDrone) -> Result<Packet, CheckError> {
+        if packet.session_id == self.0 {
+            return Ok(packet);
+        }
+        Err(CheckError::MustShortcut(packet))
+    }
+}
+
+/// Discards the packets whose hop index is still 1
+#[derive(Debug)]
+struct NotAdvanced;
+
+impl PacketCheck for NotAdvanced {
+    fn check(&self, packet: Packet, _drone: &mut FungiDrone) -> Result<Packet, CheckError> {
+        if packet.routing_header.hop_index == 1 {
+            return Err(CheckError::Debug);
+        }
+        Ok(packet)
+    }
+}
+
+/// Sends every packet to the drone's highest neighbor instead, telling the controller
+#[derive(Debug)]
+struct ToHighestNeighbor;
+
+impl PacketCheck for ToHighestNeighbor {
+    fn check(&self, packet: Packet, _drone: &mut FungiDrone) -> Result<Packet, CheckError> {
+        Err(CheckError::Custom(Box::new(Redirect(packet))))
+    }
+}
+
+#[derive(Debug)]
+struct Redirect(Packet);
+
+impl CheckOutcome for Redirect {
+    fn resolve(self: Box<Self>, drone: &FungiDrone) -> Option<Packet> {
+        let mut packet = self.0;
+        let neighbor = *drone.neighbors().last()?;
+        packet.routing_header = SourceRoutingHeader {
+            hop_index: 1,
+            hops: vec![drone.id(), neighbor],
+        };
+        drone.notify_controller(DroneEvent::PacketSent(packet.clone()));
+        Some(packet)
+    }
+}
+
+#[test]
+fn custom_checks_run_after_the_default_ones() {
+    let harness = Harness::spawn(2, &[1, 3], |drone| {
+        drone.check_pipeline_mut().push(OnlySession(7));
+    });
+
+    harness.packets.send(fragment(vec![1, 2, 3], 0)).unwrap();
+    let event = harness.events.recv_timeout(TIMEOUT).unwrap();
+    let DroneEvent::ControllerShortcut(packet) = event else {
+        panic!("expected a shortcut, got {event:?}");
+    };
+    assert_eq!(packet.routing_header.hop_index, 2);
+    assert!(harness.nothing_sent());
+
+    harness.stop();
+}
+
+#[test]
+fn moved_checks_see_the_packet_at_their_new_place() {
+    let harness = Harness::spawn(2, &[1, 3], |drone| {
+        let mut pipeline = CheckPipeline::default();
+        pipeline.insert(0, NotAdvanced);
+        drone.set_check_pipeline(pipeline);
+    });
+
+    harness.packets.send(fragment(vec![1, 2, 3], 0)).unwrap();
+    assert!(harness.nothing_sent());
+    harness.stop();
+
+    let harness = Harness::spawn(2, &[1, 3], |drone| {
+        let mut pipeline = CheckPipeline::default();
+        pipeline.insert(0, NotAdvanced).move_check(0, 2);
+        drone.set_check_pipeline(pipeline);
+    });
+
+    harness.packets.send(fragment(vec![1, 2, 3], 0)).unwrap();
+    assert!(harness.neighbors[&3].recv_timeout(TIMEOUT).is_ok());
+    harness.stop();
+}
+
+#[test]
+fn custom_outcomes_pick_the_packet_to_forward() {
+    let harness = Harness::spawn(2, &[1, 3, 4], |drone| {
+        let mut pipeline = CheckPipeline::empty();
+        pipeline.push(check::IdMatchesHop).push(ToHighestNeighbor);
+        drone.set_check_pipeline(pipeline);
+    });
+
+    harness.packets.send(fragment(vec![1, 2, 3], 0)).unwrap();
+    let packet = harness.neighbors[&4].recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(packet.routing_header.hops, vec![2, 4]);
+    assert!(matches!(packet.pack_type, PacketType::MsgFragment(_)));
+    assert!(harness.neighbors[&3].try_recv().is_err());
+
+    // Once by the outcome, once by the drone as it forwards the packet
+    let redirected = harness
+        .events
+        .try_iter()
+        .filter(|event| {
+            matches!(event, DroneEvent::PacketSent(packet) if packet.routing_header.hops == vec![2, 4])
+        })
+        .count();
+    assert_eq!(redirected, 2);
+
+    harness.stop();
+}
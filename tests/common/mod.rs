@@ -0,0 +1,134 @@
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
+use fungi_drone::FungiDrone;
+use wg_2024::controller::{DroneCommand, DroneEvent};
+use wg_2024::drone::Drone;
+use wg_2024::network::{NodeId, SourceRoutingHeader};
+use wg_2024::packet::{FloodRequest, Fragment, NodeType, Packet, PacketType};
+
+pub const TIMEOUT: Duration = Duration::from_millis(200);
+
+/// The channels around a running drone
+pub struct Harness {
+    pub events: Receiver<DroneEvent>,
+    pub commands: Sender<DroneCommand>,
+    pub packets: Sender<Packet>,
+    pub neighbors: HashMap<NodeId, Receiver<Packet>>,
+    pub drone: JoinHandle<()>,
+}
+
+impl Harness {
+    pub fn spawn(id: NodeId, neighbors: &[NodeId], setup: impl FnOnce(&mut FungiDrone)) -> Self {
+        Self::spawn_with_capacity(id, neighbors, None, setup)
+    }
+
+    /// Same as [`Harness::spawn`], but the neighbors' channels hold at most `capacity` packets if given
+    pub fn spawn_with_capacity(
+        id: NodeId,
+        neighbors: &[NodeId],
+        capacity: Option<usize>,
+        setup: impl FnOnce(&mut FungiDrone),
+    ) -> Self {
+        let (event_send, events) = unbounded();
+        let (commands, command_recv) = unbounded();
+        let (packets, packet_recv) = unbounded();
+
+        let mut packet_send = HashMap::new();
+        let mut neighbor_recv = HashMap::new();
+        for &neighbor in neighbors {
+            let (send, recv) = match capacity {
+                Some(capacity) => bounded(capacity),
+                None => unbounded(),
+            };
+            packet_send.insert(neighbor, send);
+            neighbor_recv.insert(neighbor, recv);
+        }
+
+        let mut drone = FungiDrone::new(id, event_send, command_recv, packet_recv, packet_send, 0.0);
+        setup(&mut drone);
+
+        Harness {
+            events,
+            commands,
+            packets,
+            neighbors: neighbor_recv,
+            drone: thread::spawn(move || drone.run()),
+        }
+    }
+
+    pub fn nothing_sent(&self) -> bool {
+        self.neighbors
+            .values()
+            .all(|recv| recv.recv_timeout(TIMEOUT).is_err())
+    }
+
+    /// Keeps the drone's channel busy with copies of `packet` while `f` runs, from another thread
+    pub fn busy<T>(&self, packet: Packet, f: impl FnOnce() -> T) -> T {
+        let done = AtomicBool::new(false);
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                while !done.load(Ordering::Relaxed) {
+                    if self.packets.len() >= 4096 {
+                        std::hint::spin_loop();
+                    } else if self.packets.send(packet.clone()).is_err() {
+                        break;
+                    }
+                }
+            });
+            let _done = SetOnDrop(&done);
+            f()
+        })
+    }
+
+    /// Crashes the drone and waits for its thread, panicking if the drone panicked
+    pub fn stop(self) {
+        self.commands.send(DroneCommand::Crash).unwrap();
+        drop(self.packets);
+        self.drone.join().expect("the drone thread panicked");
+    }
+}
+
+/// Sets the flag when dropped, even if the test panics
+struct SetOnDrop<'a>(&'a AtomicBool);
+
+impl Drop for SetOnDrop<'_> {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// A fragment of session 1 which has just reached `hops[1]`
+pub fn fragment(hops: Vec<NodeId>, fragment_index: u64) -> Packet {
+    Packet {
+        routing_header: SourceRoutingHeader { hop_index: 1, hops },
+        session_id: 1,
+        pack_type: PacketType::MsgFragment(Fragment {
+            fragment_index,
+            total_n_fragments: fragment_index + 1,
+            length: 0,
+            data: [0; 128],
+        }),
+    }
+}
+
+/// A FloodRequest of session 1, which has gone through the nodes of `path_trace`
+pub fn flood_request(flood_id: u64, initiator_id: NodeId, path_trace: Vec<(NodeId, NodeType)>) -> Packet {
+    Packet {
+        routing_header: SourceRoutingHeader {
+            hop_index: 0,
+            hops: Vec::new(),
+        },
+        session_id: 1,
+        pack_type: PacketType::FloodRequest(FloodRequest {
+            flood_id,
+            initiator_id,
+            path_trace,
+        }),
+    }
+}
@@ -0,0 +1,99 @@
+mod common;
+
+use std::thread;
+use std::time::Duration;
+
+use common::{fragment, Harness, TIMEOUT};
+use crossbeam_channel::{select, unbounded};
+use fungi_drone::{CrashTrigger, FaultEvent};
+use wg_2024::controller::DroneCommand;
+use wg_2024::packet::{NackType, PacketType};
+
+#[test]
+fn drain_ends_at_the_deadline_while_a_neighbor_keeps_sending() {
+    let deadline = Duration::from_millis(100);
+    let (fault_send, faults) = unbounded();
+    let harness = Harness::spawn(2, &[1, 3], |drone| {
+        drone.set_drain_timeout(Some(deadline));
+        drone.set_fault_send(fault_send);
+    });
+
+    harness.commands.send(DroneCommand::Crash).unwrap();
+    let drained = harness.busy(fragment(vec![1, 2, 3], 0), || faults.recv_timeout(deadline + TIMEOUT));
+
+    let Ok(FaultEvent::Drained { drone, summary }) = drained else {
+        panic!("expected a drain summary, got {drained:?}");
+    };
+    assert_eq!(drone, 2);
+    assert!(summary.timed_out);
+    assert!(summary.packets > 0);
+    assert_eq!(summary.heard_from, vec![1]);
+
+    // The drone's own channel still has a sender, the thread ends anyway
+    let Harness { packets, drone, .. } = harness;
+    drone.join().expect("the drone thread panicked");
+    drop(packets);
+}
+
+/// Whether fragment `fragment_index` is forwarded, rather than Nacked by a crashed drone
+fn forwards(harness: &Harness, fragment_index: u64) -> bool {
+    harness.packets.send(fragment(vec![1, 2, 3], fragment_index)).unwrap();
+    select! {
+        recv(harness.neighbors[&3]) -> _ => true,
+        recv(harness.neighbors[&1]) -> nack => {
+            let PacketType::Nack(nack) = nack.unwrap().pack_type else {
+                panic!("expected a Nack");
+            };
+            assert_eq!(nack.nack_type, NackType::ErrorInRouting(2));
+            false
+        },
+        default(TIMEOUT) => panic!("fragment {fragment_index} went nowhere"),
+    }
+}
+
+#[test]
+fn crashes_after_forwarding_enough_packets() {
+    let (fault_send, faults) = unbounded();
+    let harness = Harness::spawn(2, &[1, 3], |drone| {
+        drone.add_crash_trigger(CrashTrigger::AfterForwarded(2));
+        drone.set_fault_send(fault_send);
+    });
+
+    assert!(forwards(&harness, 0));
+    assert!(forwards(&harness, 1));
+    assert!(!forwards(&harness, 2));
+    assert!(matches!(
+        faults.recv_timeout(TIMEOUT),
+        Ok(FaultEvent::ScheduledCrash {
+            drone: 2,
+            trigger: CrashTrigger::AfterForwarded(2)
+        })
+    ));
+
+    harness.stop();
+}
+
+#[test]
+fn crashes_after_a_delay() {
+    let harness = Harness::spawn(2, &[1, 3], |drone| {
+        drone.add_crash_trigger(CrashTrigger::AfterDelay(Duration::from_millis(50)))
+    });
+
+    assert!(forwards(&harness, 0));
+    thread::sleep(Duration::from_millis(100));
+    assert!(!forwards(&harness, 1));
+
+    harness.stop();
+}
+
+#[test]
+fn endless_delay_never_crashes() {
+    let harness = Harness::spawn(2, &[1, 3], |drone| {
+        drone.add_crash_trigger(CrashTrigger::AfterDelay(Duration::MAX))
+    });
+
+    assert!(forwards(&harness, 0));
+    assert!(forwards(&harness, 1));
+
+    harness.stop();
+}
@@ -0,0 +1,118 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{fragment, Harness, TIMEOUT};
+use fungi_drone::{Corruption, FaultEvent, Reordering};
+use wg_2024::controller::DroneCommand;
+use wg_2024::packet::PacketType;
+
+#[test]
+fn reorder_window_releases_every_packet_within_jitter() {
+    let jitter = Duration::from_millis(100);
+    let harness = Harness::spawn(2, &[1, 3], |drone| {
+        drone.set_seed(5);
+        drone.set_reordering(Some(Reordering { window: 16, jitter }));
+    });
+
+    let sent_at = Instant::now();
+    for i in 0..10 {
+        harness.packets.send(fragment(vec![1, 2, 3], i)).unwrap();
+    }
+
+    let mut received = (0..10)
+        .map(|_| harness.neighbors[&3].recv_timeout(jitter + TIMEOUT).unwrap())
+        .map(|packet| match packet.pack_type {
+            PacketType::MsgFragment(fragment) => fragment.fragment_index,
+            _ => panic!("expected a fragment, got {packet:?}"),
+        })
+        .collect::<Vec<u64>>();
+    // The window isn't full, so nothing waits longer than its own jitter and the drone's poll
+    assert!(sent_at.elapsed() < jitter + Duration::from_millis(100));
+
+    assert_ne!(received, (0..10).collect::<Vec<u64>>());
+    received.sort_unstable();
+    assert_eq!(received, (0..10).collect::<Vec<u64>>());
+
+    harness.stop();
+}
+
+#[test]
+fn reorder_window_keeps_its_jitter_bound_under_load() {
+    let jitter = Duration::from_millis(50);
+    let harness = Harness::spawn(2, &[1, 3], |drone| {
+        drone.set_reordering(Some(Reordering { window: 1 << 20, jitter }))
+    });
+
+    // Packets the drone discards, so that only the packet under test goes through the window
+    let mut discarded = fragment(vec![1, 2], 0);
+    discarded.routing_header.hop_index = 5;
+
+    let received = harness.busy(discarded, || {
+        let sent_at = Instant::now();
+        harness.packets.send(fragment(vec![1, 2, 3], 0)).unwrap();
+        harness.neighbors[&3].recv_timeout(jitter + TIMEOUT).map(|_| sent_at.elapsed())
+    });
+
+    let elapsed = received.expect("the held packet never left the window");
+    assert!(elapsed < jitter + Duration::from_millis(100), "held for {elapsed:?}");
+
+    harness.stop();
+}
+
+#[test]
+fn endless_jitter_holds_packets_until_the_crash() {
+    let harness = Harness::spawn(2, &[1, 3], |drone| {
+        drone.set_reordering(Some(Reordering {
+            window: 16,
+            jitter: Duration::MAX,
+        }))
+    });
+
+    harness.packets.send(fragment(vec![1, 2, 3], 0)).unwrap();
+    assert!(harness.nothing_sent());
+
+    // Crashing releases the held packets
+    harness.commands.send(DroneCommand::Crash).unwrap();
+    harness.neighbors[&3].recv_timeout(TIMEOUT).unwrap();
+
+    harness.stop();
+}
+
+#[test]
+fn corruption_flips_only_the_bytes_in_use() {
+    let (fault_send, fault_recv) = crossbeam_channel::unbounded();
+    let harness = Harness::spawn(2, &[1, 3], |drone| {
+        drone.set_fault_send(fault_send);
+        drone.set_corruption(Some(Corruption {
+            rate: 1.0,
+            bit_error_rate: 1.0,
+            length_rate: 0.0,
+        }));
+    });
+
+    let mut packet = fragment(vec![1, 2, 3], 0);
+    if let PacketType::MsgFragment(fragment) = &mut packet.pack_type {
+        fragment.length = 4;
+    }
+    harness.packets.send(packet).unwrap();
+
+    let received = harness.neighbors[&3].recv_timeout(TIMEOUT).unwrap();
+    let PacketType::MsgFragment(fragment) = received.pack_type else {
+        panic!("expected a fragment, got {received:?}");
+    };
+    assert_eq!(fragment.length, 4);
+    assert_eq!(fragment.data[..4], [0xFF; 4]);
+    assert_eq!(fragment.data[4..], [0; 124]);
+
+    let flipped_bits = fault_recv
+        .try_iter()
+        .find_map(|event| match event {
+            FaultEvent::Corrupted { flipped_bits, .. } => Some(flipped_bits),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(flipped_bits, (0..32).collect::<Vec<usize>>());
+
+    harness.stop();
+}
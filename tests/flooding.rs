@@ -0,0 +1,207 @@
+mod common;
+
+use std::thread;
+use std::time::Duration;
+
+use common::{flood_request, Harness, TIMEOUT};
+use crossbeam_channel::unbounded;
+use fungi_drone::{
+    CrashedFloodPolicy, DebugCategory, DebugReason, Diagnostic, FloodMemoryPolicy, FLOOD_ID_WINDOW,
+};
+use wg_2024::controller::{DroneCommand, DroneEvent};
+use wg_2024::network::NodeId;
+use wg_2024::packet::{NodeType, PacketType};
+
+/// Checks the drone is still flooding after a bad request
+fn still_floods(harness: &Harness, from: NodeId, to: NodeId) {
+    harness
+        .packets
+        .send(flood_request(99, from, vec![(from, NodeType::Client)]))
+        .unwrap();
+    let packet = harness.neighbors[&to].recv_timeout(TIMEOUT).unwrap();
+    assert!(matches!(packet.pack_type, PacketType::FloodRequest(_)));
+}
+
+#[test]
+fn empty_path_trace_is_discarded() {
+    let (diagnostic_send, diagnostics) = unbounded::<Diagnostic>();
+    let harness = Harness::spawn(2, &[1, 3], |drone| drone.set_debug_sink(diagnostic_send));
+
+    harness.packets.send(flood_request(1, 1, Vec::new())).unwrap();
+    assert!(harness.nothing_sent());
+
+    let diagnostic = diagnostics.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(
+        diagnostic.reason,
+        DebugReason::MalformedFlood {
+            flood_id: 1,
+            initiator_id: 1
+        }
+    );
+    assert_eq!(diagnostic.reason.category(), DebugCategory::Flooding);
+
+    still_floods(&harness, 1, 3);
+    harness.stop();
+}
+
+#[test]
+fn unknown_previous_hop_is_discarded() {
+    let harness = Harness::spawn(2, &[1, 3], |_| ());
+
+    harness
+        .packets
+        .send(flood_request(1, 9, vec![(9, NodeType::Client)]))
+        .unwrap();
+    assert!(harness.nothing_sent());
+
+    // Seen floods are answered to the previous hop too
+    harness
+        .packets
+        .send(flood_request(1, 9, vec![(9, NodeType::Client)]))
+        .unwrap();
+    assert!(harness.nothing_sent());
+
+    still_floods(&harness, 1, 3);
+    harness.stop();
+}
+
+#[test]
+fn only_neighbor_is_not_the_sender() {
+    let harness = Harness::spawn(2, &[3], |_| ());
+
+    harness
+        .packets
+        .send(flood_request(1, 1, vec![(1, NodeType::Client)]))
+        .unwrap();
+    assert!(harness.nothing_sent());
+
+    // The only neighbor can still flood through the drone, which answers right away
+    harness
+        .packets
+        .send(flood_request(2, 3, vec![(3, NodeType::Client)]))
+        .unwrap();
+    let packet = harness.neighbors[&3].recv_timeout(TIMEOUT).unwrap();
+    assert!(matches!(packet.pack_type, PacketType::FloodResponse(_)));
+    assert_eq!(packet.routing_header.hops, vec![2, 3]);
+
+    harness.stop();
+}
+
+#[test]
+fn unroutable_response_is_shortcut() {
+    let harness = Harness::spawn(2, &[1, 3], |drone| {
+        drone.set_crashed_flood_policy(CrashedFloodPolicy::Respond)
+    });
+
+    harness.commands.send(DroneCommand::Crash).unwrap();
+    harness
+        .packets
+        .send(flood_request(1, 9, vec![(9, NodeType::Client)]))
+        .unwrap();
+
+    let event = harness.events.recv_timeout(TIMEOUT).unwrap();
+    let DroneEvent::ControllerShortcut(packet) = event else {
+        panic!("expected a shortcut, got {event:?}");
+    };
+    assert!(matches!(packet.pack_type, PacketType::FloodResponse(_)));
+    assert!(harness.nothing_sent());
+
+    harness.stop();
+}
+
+#[test]
+fn overlapping_floods_arrive_out_of_order() {
+    let harness = Harness::spawn(2, &[1, 3], |drone| {
+        drone.set_flood_memory_policy(FloodMemoryPolicy::HighestPerInitiator)
+    });
+
+    // Flood 1 overtook flood 0, both are new
+    for flood_id in [1, 0] {
+        harness
+            .packets
+            .send(flood_request(flood_id, 1, vec![(1, NodeType::Client)]))
+            .unwrap();
+        let packet = harness.neighbors[&3].recv_timeout(TIMEOUT).unwrap();
+        assert!(matches!(packet.pack_type, PacketType::FloodRequest(_)));
+    }
+
+    // Seen ids and ids older than the window are answered
+    for flood_id in [0, FLOOD_ID_WINDOW + 1, 1] {
+        harness
+            .packets
+            .send(flood_request(flood_id, 1, vec![(1, NodeType::Client)]))
+            .unwrap();
+    }
+    let packet = harness.neighbors[&1].recv_timeout(TIMEOUT).unwrap();
+    assert!(matches!(packet.pack_type, PacketType::FloodResponse(_)));
+    let packet = harness.neighbors[&3].recv_timeout(TIMEOUT).unwrap();
+    assert!(matches!(packet.pack_type, PacketType::FloodRequest(_)));
+    let packet = harness.neighbors[&1].recv_timeout(TIMEOUT).unwrap();
+    assert!(matches!(packet.pack_type, PacketType::FloodResponse(_)));
+
+    harness.stop();
+}
+
+/// Sends flood `flood_id` of initiator 1 and tells whether the drone flooded it, rather than answered it
+fn floods_again(harness: &Harness, flood_id: u64) -> bool {
+    harness
+        .packets
+        .send(flood_request(flood_id, 1, vec![(1, NodeType::Client)]))
+        .unwrap();
+    harness.neighbors[&3].recv_timeout(TIMEOUT).is_ok()
+}
+
+#[test]
+fn lru_memory_forgets_the_least_recently_seen() {
+    let harness = Harness::spawn(2, &[1, 3], |drone| {
+        drone.set_flood_memory_policy(FloodMemoryPolicy::Lru(2))
+    });
+
+    assert!(floods_again(&harness, 0));
+    assert!(floods_again(&harness, 1));
+    // Seeing flood 0 again makes flood 1 the least recently seen
+    assert!(!floods_again(&harness, 0));
+    assert!(floods_again(&harness, 2));
+    assert!(!floods_again(&harness, 0));
+    assert!(floods_again(&harness, 1));
+
+    harness.stop();
+}
+
+#[test]
+fn ttl_memory_forgets_old_floods() {
+    let harness = Harness::spawn(2, &[1, 3], |drone| {
+        drone.set_flood_memory_policy(FloodMemoryPolicy::Ttl(Duration::from_millis(100)))
+    });
+
+    assert!(floods_again(&harness, 0));
+    assert!(!floods_again(&harness, 0));
+    thread::sleep(Duration::from_millis(150));
+    assert!(floods_again(&harness, 0));
+
+    harness.stop();
+}
+
+#[test]
+fn degenerate_memories_still_detect_duplicates() {
+    for policy in [FloodMemoryPolicy::Lru(0), FloodMemoryPolicy::Ttl(Duration::ZERO)] {
+        let harness = Harness::spawn(2, &[1, 3], |drone| drone.set_flood_memory_policy(policy));
+
+        assert!(floods_again(&harness, 0), "{policy:?}");
+        assert!(!floods_again(&harness, 0), "{policy:?}");
+
+        harness.stop();
+    }
+}
+
+#[test]
+fn endless_ttl_remembers_every_flood() {
+    let harness = Harness::spawn(2, &[1, 3], |drone| {
+        drone.set_flood_memory_policy(FloodMemoryPolicy::Ttl(Duration::MAX))
+    });
+
+    assert!(floods_again(&harness, 0));
+    assert!(!floods_again(&harness, 0));
+
+    harness.stop();
+}
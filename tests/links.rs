@@ -0,0 +1,200 @@
+mod common;
+
+use std::thread;
+use std::time::Duration;
+
+use common::{fragment, Harness, TIMEOUT};
+use fungi_drone::{Latency, RetryPolicy};
+use wg_2024::controller::{DroneCommand, DroneEvent};
+use wg_2024::network::{NodeId, SourceRoutingHeader};
+use wg_2024::packet::{Ack, NackType, Packet, PacketType};
+
+/// The fragment index of a fragment, or of the fragment acknowledged by an Ack
+fn fragment_index(packet: Packet) -> u64 {
+    match packet.pack_type {
+        PacketType::MsgFragment(fragment) => fragment.fragment_index,
+        PacketType::Ack(ack) => ack.fragment_index,
+        _ => panic!("expected a fragment or an Ack, got {packet:?}"),
+    }
+}
+
+#[test]
+fn removed_neighbor_gets_no_delayed_packets() {
+    let harness = Harness::spawn(2, &[1, 3], |drone| {
+        drone.set_link_latency(3, Latency::Fixed(Duration::from_millis(100)))
+    });
+
+    harness.packets.send(fragment(vec![1, 2, 3], 0)).unwrap();
+    harness.commands.send(DroneCommand::RemoveSender(3)).unwrap();
+
+    let nack = harness.neighbors[&1].recv_timeout(TIMEOUT).unwrap();
+    let PacketType::Nack(nack) = nack.pack_type else {
+        panic!("expected a Nack, got {nack:?}");
+    };
+    assert_eq!(nack.nack_type, NackType::ErrorInRouting(3));
+    assert!(harness.neighbors[&3].recv_timeout(TIMEOUT).is_err());
+
+    harness.stop();
+}
+
+#[test]
+fn endless_latency_holds_packets_until_the_neighbor_is_removed() {
+    let harness = Harness::spawn(2, &[1, 3], |drone| {
+        drone.set_link_latency(3, Latency::Fixed(Duration::MAX))
+    });
+
+    harness.packets.send(fragment(vec![1, 2, 3], 0)).unwrap();
+    assert!(harness.nothing_sent());
+
+    harness.commands.send(DroneCommand::RemoveSender(3)).unwrap();
+    let nack = harness.neighbors[&1].recv_timeout(TIMEOUT).unwrap();
+    assert!(matches!(nack.pack_type, PacketType::Nack(_)));
+
+    harness.stop();
+}
+
+#[test]
+fn delayed_packets_leave_while_the_drone_is_busy() {
+    let latency = Duration::from_millis(20);
+    let harness = Harness::spawn(2, &[1, 3], |drone| drone.set_link_latency(3, Latency::Fixed(latency)));
+
+    let delayed = harness.busy(fragment(vec![3, 2, 1], 0), || {
+        harness.packets.send(fragment(vec![1, 2, 3], 0)).unwrap();
+        harness.neighbors[&3].recv_timeout(latency + TIMEOUT)
+    });
+    assert!(delayed.is_ok());
+
+    harness.stop();
+}
+
+#[test]
+fn full_neighbor_gets_the_queued_packets_in_order() {
+    let harness = Harness::spawn_with_capacity(2, &[1, 3], Some(1), |_| ());
+
+    for i in 0..4 {
+        harness.packets.send(fragment(vec![1, 2, 3], i)).unwrap();
+    }
+    thread::sleep(Duration::from_millis(50));
+
+    for i in 0..4 {
+        assert_eq!(fragment_index(harness.neighbors[&3].recv_timeout(TIMEOUT).unwrap()), i);
+    }
+    assert!(harness.nothing_sent());
+
+    harness.stop();
+}
+
+/// An Ack for fragment `fragment_index` which has just reached `hops[1]`
+fn ack(hops: Vec<NodeId>, fragment_index: u64) -> Packet {
+    Packet {
+        routing_header: SourceRoutingHeader { hop_index: 1, hops },
+        session_id: 1,
+        pack_type: PacketType::Ack(Ack { fragment_index }),
+    }
+}
+
+#[test]
+fn given_up_fragments_are_nacked() {
+    let harness = Harness::spawn_with_capacity(2, &[1, 3], Some(1), |drone| {
+        drone.set_retry_policy(RetryPolicy::no_retry())
+    });
+
+    harness.packets.send(fragment(vec![1, 2, 3], 0)).unwrap();
+    harness.packets.send(fragment(vec![1, 2, 3], 1)).unwrap();
+
+    let nack = harness.neighbors[&1].recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(nack.routing_header.hops, vec![2, 1]);
+    let PacketType::Nack(nack) = nack.pack_type else {
+        panic!("expected a Nack, got {nack:?}");
+    };
+    assert_eq!(nack.nack_type, NackType::Dropped);
+    assert_eq!(nack.fragment_index, 1);
+
+    harness.stop();
+}
+
+#[test]
+fn endless_retry_interval_never_retries() {
+    let harness = Harness::spawn_with_capacity(2, &[1, 3], Some(1), |drone| {
+        drone.set_retry_policy(RetryPolicy {
+            interval: Duration::MAX,
+            ..RetryPolicy::default()
+        })
+    });
+
+    harness.packets.send(fragment(vec![1, 2, 3], 0)).unwrap();
+    harness.packets.send(fragment(vec![1, 2, 3], 1)).unwrap();
+    thread::sleep(Duration::from_millis(50));
+
+    assert_eq!(fragment_index(harness.neighbors[&3].recv_timeout(TIMEOUT).unwrap()), 0);
+    assert!(harness.nothing_sent());
+
+    harness.stop();
+}
+
+#[test]
+fn acks_overtake_queued_fragments() {
+    let harness = Harness::spawn_with_capacity(2, &[1, 3], Some(1), |_| ());
+
+    harness.packets.send(fragment(vec![1, 2, 3], 0)).unwrap();
+    harness.packets.send(fragment(vec![1, 2, 3], 1)).unwrap();
+    harness.packets.send(ack(vec![1, 2, 3], 7)).unwrap();
+    thread::sleep(Duration::from_millis(50));
+
+    let received = (0..3)
+        .map(|_| harness.neighbors[&3].recv_timeout(TIMEOUT).unwrap())
+        .map(|packet| (matches!(packet.pack_type, PacketType::Ack(_)), fragment_index(packet)))
+        .collect::<Vec<_>>();
+    assert_eq!(received, vec![(false, 0), (true, 7), (false, 1)]);
+
+    harness.stop();
+}
+
+#[test]
+fn full_queue_gives_up_the_lowest_priority() {
+    let harness = Harness::spawn_with_capacity(2, &[1, 3], Some(1), |drone| {
+        drone.set_retry_policy(RetryPolicy {
+            capacity: 2,
+            max_retries: None,
+            max_age: None,
+            ..RetryPolicy::default()
+        })
+    });
+
+    // Fragment 0 fills the channel, fragments 1 and 2 fill the queue
+    for i in 0..3 {
+        harness.packets.send(fragment(vec![1, 2, 3], i)).unwrap();
+    }
+    // The Ack takes the place of the newest fragment, fragment 3 has nothing lower to replace
+    harness.packets.send(ack(vec![1, 2, 3], 7)).unwrap();
+    harness.packets.send(fragment(vec![1, 2, 3], 3)).unwrap();
+    thread::sleep(Duration::from_millis(50));
+
+    let received = (0..3)
+        .map(|_| fragment_index(harness.neighbors[&3].recv_timeout(TIMEOUT).unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(received, vec![0, 7, 1]);
+
+    // The fragments given up are Nacked back to the source
+    let nacked = (0..2)
+        .map(|_| harness.neighbors[&1].recv_timeout(TIMEOUT).unwrap())
+        .map(|packet| match packet.pack_type {
+            PacketType::Nack(nack) if nack.nack_type == NackType::Dropped => nack.fragment_index,
+            _ => panic!("expected a Dropped Nack, got {packet:?}"),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(nacked, vec![2, 3]);
+    assert!(harness.nothing_sent());
+
+    let given_up = harness
+        .events
+        .try_iter()
+        .filter_map(|event| match event {
+            DroneEvent::PacketDropped(packet) => Some(fragment_index(packet)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(given_up, vec![2, 3]);
+
+    harness.stop();
+}
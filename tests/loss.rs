@@ -0,0 +1,51 @@
+mod common;
+
+use common::{fragment, Harness, TIMEOUT};
+use fungi_drone::{Bernoulli, GilbertElliott, LossModel};
+use rand::SeedableRng;
+use rand_xoshiro::Xoshiro256PlusPlus;
+use wg_2024::packet::PacketType;
+
+/// Sends 40 fragments through a lossy drone seeded with `seed`, returns the indexes of the ones forwarded
+fn forwarded_fragments(seed: u64) -> Vec<u64> {
+    let harness = Harness::spawn(2, &[1, 3], |drone| {
+        drone.set_seed(seed);
+        drone.set_loss_model(Bernoulli::new(0.5));
+    });
+
+    for i in 0..40 {
+        harness.packets.send(fragment(vec![1, 2, 3], i)).unwrap();
+    }
+    let forwarded = std::iter::from_fn(|| harness.neighbors[&3].recv_timeout(TIMEOUT).ok())
+        .map(|packet| match packet.pack_type {
+            PacketType::MsgFragment(fragment) => fragment.fragment_index,
+            _ => panic!("expected a fragment, got {packet:?}"),
+        })
+        .collect();
+
+    harness.stop();
+    forwarded
+}
+
+#[test]
+fn same_seed_drops_the_same_fragments() {
+    let forwarded = forwarded_fragments(42);
+    assert!(!forwarded.is_empty() && forwarded.len() < 40);
+    assert_eq!(forwarded, forwarded_fragments(42));
+    assert_ne!(forwarded, forwarded_fragments(43));
+}
+
+#[test]
+fn gilbert_elliott_drops_in_bursts() {
+    let mut rng = Xoshiro256PlusPlus::seed_from_u64(7);
+    let mut model = GilbertElliott::gilbert(0.05, 0.25);
+
+    let drops = (0..10_000).map(|_| model.dropped(&mut rng)).collect::<Vec<bool>>();
+    let dropped = drops.iter().filter(|&&dropped| dropped).count();
+    let bursts = drops.windows(2).filter(|pair| !pair[0] && pair[1]).count();
+
+    // A burst lasts 1 / p_bad_to_good fragments on average, the link is bad 0.05 / 0.3 of the time
+    let mean_burst = dropped as f64 / bursts as f64;
+    assert!((3.0..5.0).contains(&mean_burst), "mean burst of {mean_burst}");
+    assert!((1_300..2_000).contains(&dropped), "{dropped} fragments dropped");
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+use common::{fragment, Harness, TIMEOUT};
+use fungi_drone::{Byzantine, NackKind, PacketKind, PrometheusExporter};
+
+#[test]
+fn counts_by_type_neighbor_and_nack_kind() {
+    let mut metrics = None;
+    let harness = Harness::spawn(2, &[1, 3], |drone| metrics = Some(drone.metrics()));
+    let metrics = metrics.unwrap();
+
+    harness.packets.send(fragment(vec![1, 2, 3], 0)).unwrap();
+    harness.packets.send(fragment(vec![1, 2, 9], 0)).unwrap();
+    harness.neighbors[&3].recv_timeout(TIMEOUT).unwrap();
+    harness.neighbors[&1].recv_timeout(TIMEOUT).unwrap();
+
+    let snapshot = metrics.snapshot();
+    assert_eq!(snapshot.drone, 2);
+    assert_eq!(snapshot.received[&(Some(1), PacketKind::MsgFragment)], 2);
+    assert_eq!(snapshot.forwarded[&(3, PacketKind::MsgFragment)], 1);
+    assert_eq!(snapshot.forwarded[&(1, PacketKind::Nack)], 1);
+    assert_eq!(snapshot.nacks[&NackKind::ErrorInRouting], 1);
+    assert_eq!(snapshot.received_from(1), 2);
+    assert_eq!(snapshot.forwarded_of(PacketKind::MsgFragment), 1);
+    assert_eq!(snapshot.dropped, 0);
+
+    harness.stop();
+}
+
+#[test]
+fn swallowed_fragments_are_dropped() {
+    let mut metrics = None;
+    let harness = Harness::spawn(2, &[1, 3], |drone| {
+        drone.set_byzantine(Some(Byzantine::Blackhole));
+        metrics = Some(drone.metrics());
+    });
+
+    harness.packets.send(fragment(vec![1, 2, 3], 0)).unwrap();
+    assert!(harness.nothing_sent());
+    assert_eq!(metrics.unwrap().snapshot().dropped, 1);
+
+    harness.stop();
+}
+
+#[test]
+fn prometheus_endpoint_serves_the_counters() {
+    let mut exporter = PrometheusExporter::new();
+    let harness = Harness::spawn(2, &[1, 3], |drone| exporter.add(drone.metrics()));
+
+    harness.packets.send(fragment(vec![1, 2, 3], 0)).unwrap();
+    harness.neighbors[&3].recv_timeout(TIMEOUT).unwrap();
+
+    let addr = exporter.serve(0).unwrap();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"GET /metrics HTTP/1.1\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response.contains("# TYPE fungi_drone_packets_forwarded_total counter"));
+    assert!(response.contains(
+        "fungi_drone_packets_forwarded_total{drone=\"2\",neighbor=\"3\",packet_type=\"msg_fragment\"} 1"
+    ));
+    assert!(response.contains(
+        "fungi_drone_packets_received_total{drone=\"2\",neighbor=\"1\",packet_type=\"msg_fragment\"} 1"
+    ));
+
+    harness.stop();
+}
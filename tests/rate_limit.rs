@@ -0,0 +1,134 @@
+mod common;
+
+use std::thread;
+use std::time::Duration;
+
+use common::{flood_request, Harness, TIMEOUT};
+use fungi_drone::{FloodRateLimit, ThrottleAction};
+use wg_2024::controller::DroneEvent;
+use wg_2024::packet::{NodeType, PacketType};
+
+/// A limit of one flood per initiator, which never refills
+fn one_flood(action: ThrottleAction) -> Option<FloodRateLimit> {
+    Some(FloodRateLimit {
+        burst: 1,
+        per_second: 0.0,
+        action,
+    })
+}
+
+#[test]
+fn answered_floods_are_not_dropped() {
+    let mut metrics = None;
+    let harness = Harness::spawn(2, &[1, 3], |drone| {
+        drone.set_flood_rate_limit(one_flood(ThrottleAction::Respond));
+        metrics = Some(drone.metrics());
+    });
+
+    for flood_id in 0..2 {
+        harness
+            .packets
+            .send(flood_request(flood_id, 1, vec![(1, NodeType::Client)]))
+            .unwrap();
+    }
+    let packet = harness.neighbors[&3].recv_timeout(TIMEOUT).unwrap();
+    assert!(matches!(packet.pack_type, PacketType::FloodRequest(_)));
+    let packet = harness.neighbors[&1].recv_timeout(TIMEOUT).unwrap();
+    assert!(matches!(packet.pack_type, PacketType::FloodResponse(_)));
+
+    assert!(harness
+        .events
+        .try_iter()
+        .all(|event| !matches!(event, DroneEvent::PacketDropped(_))));
+    assert_eq!(metrics.unwrap().snapshot().dropped, 0);
+
+    harness.stop();
+}
+
+#[test]
+fn dropped_floods_are_reported_without_the_request_log() {
+    let mut metrics = None;
+    let harness = Harness::spawn(2, &[1, 3], |drone| {
+        drone.set_flood_rate_limit(one_flood(ThrottleAction::Drop));
+        drone.disable_request_log();
+        metrics = Some(drone.metrics());
+    });
+
+    for flood_id in 0..2 {
+        harness
+            .packets
+            .send(flood_request(flood_id, 1, vec![(1, NodeType::Client)]))
+            .unwrap();
+    }
+    harness.neighbors[&3].recv_timeout(TIMEOUT).unwrap();
+    assert!(harness.nothing_sent());
+
+    let dropped = harness
+        .events
+        .try_iter()
+        .filter_map(|event| match event {
+            DroneEvent::PacketDropped(packet) => Some(packet),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(dropped.len(), 1);
+    assert!(matches!(&dropped[0].pack_type, PacketType::FloodRequest(req) if req.flood_id == 1));
+    assert_eq!(metrics.unwrap().snapshot().dropped, 1);
+
+    harness.stop();
+}
+
+#[test]
+fn answered_floods_reach_the_debug_shortcut() {
+    let harness = Harness::spawn(2, &[1, 3], |drone| {
+        drone.set_flood_rate_limit(one_flood(ThrottleAction::Respond));
+        drone.set_debug_shortcut();
+    });
+
+    for flood_id in 0..2 {
+        harness
+            .packets
+            .send(flood_request(flood_id, 1, vec![(1, NodeType::Client)]))
+            .unwrap();
+    }
+    harness.neighbors[&1].recv_timeout(TIMEOUT).unwrap();
+
+    let shortcut = harness.events.try_iter().find_map(|event| match event {
+        DroneEvent::ControllerShortcut(packet) => Some(packet),
+        _ => None,
+    });
+    let Some(packet) = shortcut else {
+        panic!("expected the throttled request to be shortcut");
+    };
+    assert!(matches!(packet.pack_type, PacketType::FloodRequest(req) if req.flood_id == 1));
+
+    harness.stop();
+}
+
+#[test]
+fn bucket_refills_over_time() {
+    let harness = Harness::spawn(2, &[1, 3], |drone| {
+        drone.set_flood_rate_limit(Some(FloodRateLimit {
+            burst: 2,
+            per_second: 2.0,
+            action: ThrottleAction::Drop,
+        }))
+    });
+    let flood = |flood_id| {
+        harness
+            .packets
+            .send(flood_request(flood_id, 1, vec![(1, NodeType::Client)]))
+            .unwrap();
+        harness.neighbors[&3].recv_timeout(TIMEOUT).is_ok()
+    };
+
+    // The burst is spent at once, then one token comes back every 500ms
+    assert!(flood(0));
+    assert!(flood(1));
+    assert!(!flood(2));
+    thread::sleep(Duration::from_millis(400));
+    assert!(flood(3));
+    assert!(!flood(4));
+
+    harness.stop();
+}
@@ -0,0 +1,156 @@
+mod common;
+
+use std::fs;
+use std::thread;
+use std::time::Duration;
+
+use common::{flood_request, fragment, Harness, TIMEOUT};
+use fungi_drone::{replay, Bernoulli, FungiDrone, Recovery, Trace};
+use wg_2024::controller::DroneCommand;
+use wg_2024::network::NodeId;
+use wg_2024::packet::{NodeType, PacketType};
+
+/// Runs drone 2, captures it while `feed` drives it, and reads the capture back
+fn capture(
+    name: &str,
+    neighbors: &[NodeId],
+    setup: impl FnOnce(&mut FungiDrone),
+    feed: impl FnOnce(&Harness),
+) -> Trace {
+    let path = std::env::temp_dir().join(format!("fungi-replay-{name}-{}.jsonl", std::process::id()));
+    let harness = Harness::spawn(2, neighbors, |drone| {
+        drone.set_seed(11);
+        setup(drone);
+        drone.capture_to_file(&path).unwrap();
+    });
+
+    feed(&harness);
+    harness.stop();
+
+    let trace = Trace::read_file(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    trace
+}
+
+/// A lossy drone which loses a neighbor halfway through
+fn capture_lossy(name: &str) -> Trace {
+    capture(
+        name,
+        &[1, 3],
+        |drone| drone.set_loss_model(Bernoulli::new(0.5)),
+        |harness| {
+            for i in 0..10 {
+                harness.packets.send(fragment(vec![1, 2, 3], i)).unwrap();
+                harness.neighbors[&1].recv_timeout(TIMEOUT).ok();
+            }
+            harness.commands.send(DroneCommand::RemoveSender(3)).unwrap();
+            for i in 10..20 {
+                harness.packets.send(fragment(vec![1, 2, 3], i)).unwrap();
+            }
+        },
+    )
+}
+
+#[test]
+fn replay_matches_the_capture() {
+    let trace = capture_lossy("match");
+
+    let report = replay(&trace, |drone| drone.set_loss_model(Bernoulli::new(0.5))).unwrap();
+    assert!(report.matches(), "first mismatch at {:?}", report.first_mismatch);
+    assert_eq!(report.actual.len(), report.expected.len());
+    assert!(report.expected.len() >= 20);
+}
+
+#[test]
+fn replay_reports_a_mismatch() {
+    let trace = capture_lossy("mismatch");
+
+    // Without the loss model every fragment goes through
+    let report = replay(&trace, |_| ()).unwrap();
+    assert!(!report.matches());
+}
+
+#[test]
+fn replay_matches_a_flood_fan_out() {
+    let neighbors = [1, 3, 4, 5, 6, 7];
+    let trace = capture("flood", &neighbors, |_| (), |harness| {
+        for flood_id in 0..10 {
+            let path_trace = vec![(1, NodeType::Client)];
+            harness.packets.send(flood_request(flood_id, 1, path_trace)).unwrap();
+            harness.neighbors[&7].recv_timeout(TIMEOUT).unwrap();
+        }
+    });
+
+    let report = replay(&trace, |_| ()).unwrap();
+    assert!(report.matches(), "first mismatch at {:?}", report.first_mismatch);
+
+    let floods = report
+        .expected
+        .iter()
+        .filter(|sent| matches!(sent.packet.pack_type, PacketType::FloodRequest(_)))
+        .count();
+    assert_eq!(floods, 10 * (neighbors.len() - 1));
+}
+
+#[test]
+fn replay_keeps_the_order_of_inputs_while_crashed() {
+    let trace = capture("crashed", &[1, 3], |_| (), |harness| {
+        harness.commands.send(DroneCommand::Crash).unwrap();
+        harness.packets.send(fragment(vec![1, 2, 3], 0)).unwrap();
+        harness.neighbors[&1].recv_timeout(TIMEOUT).unwrap();
+
+        harness.commands.send(DroneCommand::RemoveSender(1)).unwrap();
+        thread::sleep(Duration::from_millis(50));
+        harness.packets.send(fragment(vec![1, 2, 3], 1)).unwrap();
+    });
+
+    let report = replay(&trace, |_| ()).unwrap();
+    assert!(report.matches(), "first mismatch at {:?}", report.first_mismatch);
+    // Only the first fragment is answered with a Nack, the second comes after its sender was removed
+    assert_eq!(report.expected.len(), 1);
+}
+
+#[test]
+fn replay_continues_after_a_recovery() {
+    let recovery = Recovery {
+        recover_after: Some(Duration::from_millis(50)),
+        reset_flood_ids: false,
+    };
+    let trace = capture("recovery", &[1, 3], |drone| drone.set_recovery(recovery), |harness| {
+        harness.commands.send(DroneCommand::Crash).unwrap();
+        harness.packets.send(fragment(vec![1, 2, 3], 0)).unwrap();
+        harness.neighbors[&1].recv_timeout(TIMEOUT).unwrap();
+
+        thread::sleep(Duration::from_millis(150));
+        harness.packets.send(fragment(vec![1, 2, 3], 1)).unwrap();
+        harness.neighbors[&3].recv_timeout(TIMEOUT).unwrap();
+    });
+
+    let report = replay(&trace, |drone| drone.set_recovery(recovery)).unwrap();
+    assert!(report.matches(), "first mismatch at {:?}", report.first_mismatch);
+    assert_eq!(report.expected.len(), 2);
+}
+
+#[test]
+fn replay_follows_a_seed_set_after_the_capture_started() {
+    let path = std::env::temp_dir().join(format!("fungi-replay-reseeded-{}.jsonl", std::process::id()));
+    let harness = Harness::spawn(2, &[1, 3], |drone| {
+        drone.set_seed(11);
+        drone.capture_to_file(&path).unwrap();
+        drone.set_seed(7);
+        drone.set_loss_model(Bernoulli::new(0.5));
+    });
+    for i in 0..20 {
+        harness.packets.send(fragment(vec![1, 2, 3], i)).unwrap();
+    }
+    thread::sleep(Duration::from_millis(50));
+    harness.stop();
+
+    let trace = Trace::read_file(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(trace.header.seed, 11);
+
+    let report = replay(&trace, |drone| drone.set_loss_model(Bernoulli::new(0.5))).unwrap();
+    assert!(report.matches(), "first mismatch at {:?}", report.first_mismatch);
+    assert_eq!(report.expected.len(), 20);
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use common::{fragment, TIMEOUT};
+use fungi_drone::sim::{parse_config, Network, SimError};
+
+const CHAIN: &str = r#"
+[[drone]]
+id = 2
+connected_node_ids = [1, 3]
+pdr = 0.0
+
+[[drone]]
+id = 3
+connected_node_ids = [2, 4]
+pdr = 0.0
+
+[[client]]
+id = 1
+connected_drone_ids = [2]
+
+[[server]]
+id = 4
+connected_drone_ids = [3]
+"#;
+
+#[test]
+fn fragment_crosses_the_network() {
+    let config = parse_config(CHAIN).unwrap();
+    let mut ids = Vec::new();
+    let network = Network::spawn_with(&config, |drone| {
+        drone.set_seed(5);
+        ids.push(drone.id());
+    })
+    .unwrap();
+
+    ids.sort_unstable();
+    assert_eq!(ids, vec![2, 3]);
+
+    network.clients[&1].packet_send[&2].send(fragment(vec![1, 2, 3, 4], 0)).unwrap();
+
+    let received = network.servers[&4].packet_recv.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(received.routing_header.hop_index, 3);
+    assert!(network.drones[&2].event_recv.recv_timeout(TIMEOUT).is_ok());
+
+    network.shutdown().unwrap();
+}
+
+#[test]
+fn unknown_neighbor_is_rejected() {
+    let config = parse_config(&CHAIN.replace("[3]", "[9]")).unwrap();
+
+    let error = Network::spawn(&config).unwrap_err();
+    assert!(matches!(error, SimError::UnknownNeighbor { node: 4, neighbor: 9 }));
+}